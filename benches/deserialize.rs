@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use wikiquery::responses::{self, borrowed};
+
+fn category_members_body(count: usize) -> String
+{
+    let members = (0..count)
+        .map(|i| format!(
+            "{{\"pageid\":{},\"ns\":0,\"title\":\"List of colors number {}\",\"sortkey\":\"0403063f394d4f4d044533042d453f454b4d011501c4dc{:06x}\",\"sortkeyprefix\":\"colors {}\",\"type\":\"page\",\"timestamp\":\"2019-01-30T18:32:56Z\"}}",
+            i, i, i, i
+        ))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{\"batchcomplete\":true,\"query\":{{\"categorymembers\":[{}]}}}}", members)
+}
+
+fn pages_body(count: usize) -> String
+{
+    let extract = "Death is the permanent cessation of all biological functions that sustain a living organism. ".repeat(20);
+
+    let pages = (0..count)
+        .map(|i| format!(
+            "{{\"ns\":0,\"title\":\"Page {}\",\"pageid\":{},\"contentmodel\":\"wikitext\",\"pagelanguage\":\"en\",\"touched\":\"2019-09-30T00:00:00Z\",\"lastrevid\":{},\"length\":{},\"description\":\"permanent cessation of vital functions\",\"descriptionsource\":\"central\",\"extract\":\"{}\"}}",
+            i, i, i, i, extract
+        ))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{\"batchcomplete\":true,\"query\":{{\"pages\":[{}]}}}}", pages)
+}
+
+fn bench_category_members(c: &mut Criterion)
+{
+    let body = category_members_body(5000);
+    let mut group = c.benchmark_group("category_members");
+
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    group.bench_function("owned", |b| b.iter(|| {
+        serde_json::from_str::<responses::Query>(&body).unwrap()
+    }));
+    group.bench_function("borrowed", |b| b.iter(|| {
+        serde_json::from_str::<borrowed::Query>(&body).unwrap()
+    }));
+    group.finish();
+}
+
+fn bench_pages(c: &mut Criterion)
+{
+    let body = pages_body(500);
+    let mut group = c.benchmark_group("pages");
+
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    group.bench_function("owned", |b| b.iter(|| {
+        serde_json::from_str::<responses::Query>(&body).unwrap()
+    }));
+    group.bench_function("borrowed", |b| b.iter(|| {
+        serde_json::from_str::<borrowed::Query>(&body).unwrap()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_category_members, bench_pages);
+criterion_main!(benches);
@@ -0,0 +1,438 @@
+//! Runs pages queries for any number of titles or page ids.
+//!
+//! The api accepts at most [`MAX_TITLES`] titles or ids per request, or
+//! [`MAX_TITLES_HIGH_LIMITS`] for accounts with `apihighlimits`, and returns
+//! extracts for at most [`MAX_EXTRACTS`] pages at once. [`PageBatches`] splits
+//! the titles or ids of a pages query into batches within those limits and
+//! builds one query per batch. A chunk size of [`Limit::Max`] follows the
+//! [`Rights`] of the account. Once every batch has been run through all of
+//! its continuations, [`PageBatches::merge`] combines the responses into
+//! [`Pages`], which maps each input to its page through the `normalized` and
+//! `redirects` blocks.
+//!
+//! [`Client::execute_pages`] does all of this in one call.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use wikiquery::batch::PageBatches;
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::{encode_value, Query};
+//!
+//! let mut wiki = FakeWiki::new();
+//! let titles: Vec<String> = (0..120).map(|i| format!("Page {}", i)).collect();
+//!
+//! for title in &titles
+//! {
+//!     wiki.add_page(FakePage::new(title.as_str()));
+//! }
+//!
+//! let client = Client::new(wiki);
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles(encode_value(&titles.join("|")))
+//!     .info();
+//!
+//! let batches = PageBatches::new(&query);
+//! let pages = client.execute_pages(&batches).unwrap();
+//!
+//! assert_eq!(batches.queries().len(), 3);
+//! assert_eq!(pages.pages.len(), 120);
+//! assert_eq!(pages.get("Page 42").unwrap().title, "Page 42");
+//! # }
+//! ```
+//!
+//! [`MAX_TITLES`]: ../status/constant.MAX_TITLES.html
+//! [`MAX_TITLES_HIGH_LIMITS`]: constant.MAX_TITLES_HIGH_LIMITS.html
+//! [`MAX_EXTRACTS`]: constant.MAX_EXTRACTS.html
+//! [`PageBatches`]: struct.PageBatches.html
+//! [`PageBatches::merge`]: struct.PageBatches.html#method.merge
+//! [`Pages`]: struct.Pages.html
+//! [`Client::execute_pages`]: ../client/struct.Client.html#method.execute_pages
+//! [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+//! [`Rights`]: ../rights/struct.Rights.html
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::requests::{decode_value, Limit, Query};
+use crate::responses::merge::PageMerger;
+use crate::responses::{self, pages, Normalized, Redirect};
+use crate::rights::Rights;
+use crate::status::MAX_TITLES;
+
+/// The maximum number of titles or ids the api accepts in one request with
+/// `apihighlimits`.
+pub const MAX_TITLES_HIGH_LIMITS: usize = 500;
+
+/// The maximum number of pages the api returns extracts for in one request.
+pub const MAX_EXTRACTS: usize = 20;
+
+/// The titles or page ids of a pages query, split into batches.
+#[derive(Debug, Clone)]
+pub struct PageBatches
+{
+    params: BTreeMap<String, String>,
+    key: &'static str,
+    inputs: Vec<String>,
+    chunk_size: Limit,
+    high_limits: bool,
+}
+
+impl PageBatches
+{
+    /// Splits the `titles` of the query, or its `pageids` if it has no titles.
+    ///
+    /// Duplicate inputs are only sent once. Continue params are dropped, each
+    /// batch is run from its start.
+    pub fn new(query: &Query) -> PageBatches
+    {
+        let mut params = query.spec();
+        let key = if params.contains_key("titles") || !params.contains_key("pageids") { "titles" } else { "pageids" };
+        let mut seen = HashSet::new();
+        let inputs = params.remove(key)
+            .unwrap_or_default()
+            .split('|')
+            .filter(|input| !input.is_empty() && seen.insert(input.to_string()))
+            .map(str::to_string)
+            .collect();
+
+        PageBatches {
+            params,
+            key,
+            inputs,
+            chunk_size: Limit::Value(MAX_TITLES),
+            high_limits: false,
+        }
+    }
+
+    /// Sets the number of titles or ids sent per request.
+    ///
+    /// Defaults to [`MAX_TITLES`]. [`Limit::Max`] sends as many as the account
+    /// may, see [`PageBatches::rights`]. It's lowered to the `exlimit` of the
+    /// query, or [`MAX_EXTRACTS`], when extracts are requested.
+    ///
+    /// [`MAX_TITLES`]: ../status/constant.MAX_TITLES.html
+    /// [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+    /// [`PageBatches::rights`]: struct.PageBatches.html#method.rights
+    /// [`MAX_EXTRACTS`]: constant.MAX_EXTRACTS.html
+    pub fn chunk_size<L: Into<Limit>>(&mut self, chunk_size: L) -> &mut Self
+    {
+        self.chunk_size = chunk_size.into();
+        self
+    }
+
+    /// Sends [`MAX_TITLES_HIGH_LIMITS`] titles or ids per request, for
+    /// accounts with `apihighlimits`.
+    ///
+    /// The rights of the account aren't checked, a chunk size of
+    /// [`Limit::Max`] follows them instead.
+    ///
+    /// [`MAX_TITLES_HIGH_LIMITS`]: constant.MAX_TITLES_HIGH_LIMITS.html
+    /// [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+    pub fn high_limits(&mut self) -> &mut Self
+    {
+        self.chunk_size(MAX_TITLES_HIGH_LIMITS)
+    }
+
+    /// Resolves a chunk size of [`Limit::Max`] with the rights of the account.
+    ///
+    /// [`Client::execute_pages`] does this with the rights of its account.
+    ///
+    /// [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+    /// [`Client::execute_pages`]: ../client/struct.Client.html#method.execute_pages
+    pub fn rights(&mut self, rights: &Rights) -> &mut Self
+    {
+        self.high_limits = rights.high_limits();
+        self
+    }
+
+    /// The chunk size, as it was set.
+    pub fn limit(&self) -> Limit
+    {
+        self.chunk_size
+    }
+
+    /// The titles or ids, as they were given to the query.
+    pub fn inputs(&self) -> &[String]
+    {
+        &self.inputs
+    }
+
+    /// Whether the inputs are page ids rather than titles.
+    pub fn is_page_ids(&self) -> bool
+    {
+        self.key == "pageids"
+    }
+
+    /// The number of titles or ids actually sent per request.
+    pub fn batch_size(&self) -> usize
+    {
+        let max = if self.high_limits { MAX_TITLES_HIGH_LIMITS } else { MAX_TITLES };
+        let chunk_size = self.chunk_size.resolve(max).max(1);
+        let extracts = self.params.get("prop")
+            .is_some_and(|props| props.split('|').any(|prop| prop == "extracts"));
+
+        if !extracts
+        {
+            return chunk_size;
+        }
+
+        let ex_limit = self.params.get("exlimit")
+            .and_then(|limit| limit.parse().ok())
+            .unwrap_or(MAX_EXTRACTS);
+
+        chunk_size.min(ex_limit.clamp(1, MAX_EXTRACTS))
+    }
+
+    /// Builds one query per batch.
+    pub fn queries(&self) -> Vec<Query<'static>>
+    {
+        self.inputs
+            .chunks(self.batch_size())
+            .map(|chunk| {
+                let mut params = self.params.clone();
+
+                params.insert(self.key.to_string(), chunk.join("|"));
+
+                Query::from_params(params)
+            })
+            .collect()
+    }
+
+    /// Combines the responses of every batch, in the order they were received.
+    ///
+    /// Pages split across continuations are merged, see [`PageMerger`]. A page
+    /// reached from several inputs is only listed once.
+    ///
+    /// [`PageMerger`]: ../responses/merge/struct.PageMerger.html
+    pub fn merge<I>(&self, responses: I) -> Pages
+        where I: IntoIterator<Item = responses::Query>
+    {
+        let mut merger = PageMerger::new();
+        let mut merged = Pages {
+            pages: Vec::new(),
+            normalized: Vec::new(),
+            redirects: Vec::new(),
+            inputs: self.inputs.clone(),
+            page_ids: self.is_page_ids(),
+        };
+        let mut seen = HashSet::new();
+        let mut add = |merged: &mut Pages, pages: Vec<pages::Data>| {
+            for page in pages
+            {
+                if seen.insert((page.page_id, page.title.clone()))
+                {
+                    merged.pages.push(page);
+                }
+            }
+        };
+
+        for mut response in responses
+        {
+            merged.normalized.extend(response.query.normalized.take().unwrap_or_default());
+            merged.redirects.extend(response.query.redirects.take().unwrap_or_default());
+
+            let pages = merger.push(&mut response);
+
+            add(&mut merged, pages);
+        }
+
+        add(&mut merged, merger.finish());
+
+        merged
+    }
+}
+
+/// The merged pages of every batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pages
+{
+    pub pages: Vec<pages::Data>,
+    pub normalized: Vec<Normalized>,
+    pub redirects: Vec<Redirect>,
+    inputs: Vec<String>,
+    page_ids: bool,
+}
+
+impl Pages
+{
+    /// The titles or ids, as they were given to the query.
+    pub fn inputs(&self) -> &[String]
+    {
+        &self.inputs
+    }
+
+    /// The title an input title resolves to, following its normalization and
+    /// then its redirect.
+    ///
+    /// Percent encoded inputs are decoded first.
+    pub fn resolve(&self, input: &str) -> String
+    {
+        let mut title = decode_value(input);
+
+        if let Some(normalized) = self.normalized.iter().find(|n| n.from == title)
+        {
+            title = normalized.to.clone();
+        }
+
+        if let Some(redirect) = self.redirects.iter().find(|r| r.from == title)
+        {
+            title = redirect.to.clone();
+        }
+
+        title
+    }
+
+    /// The page of an input title or id.
+    ///
+    /// Ids are matched to the page with that id, so the id of a resolved
+    /// redirect has no page.
+    pub fn get(&self, input: &str) -> Option<&pages::Data>
+    {
+        if self.page_ids
+        {
+            let id = input.parse().ok();
+
+            return self.pages.iter().find(|page| page.page_id.is_some() && page.page_id == id);
+        }
+
+        let title = self.resolve(input);
+
+        self.pages.iter().find(|page| page.title == title)
+    }
+
+    /// Every input with its page, in the order they were given.
+    pub fn by_input(&self) -> Vec<(&str, Option<&pages::Data>)>
+    {
+        let index: HashMap<&str, &pages::Data> = self.pages.iter()
+            .map(|page| (page.title.as_str(), page))
+            .collect();
+
+        self.inputs.iter()
+            .map(|input| {
+                let page = if self.page_ids
+                {
+                    self.get(input)
+                }
+                else
+                {
+                    index.get(self.resolve(input).as_str()).copied()
+                };
+
+                (input.as_str(), page)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod batch_tests
+{
+    use super::*;
+    use crate::test::helpers::*;
+
+    fn response(body: &str) -> responses::Query
+    {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn splits_inputs_into_batches()
+    {
+        let titles: Vec<String> = (0..1200).map(|i| format!("Page%20{}", i)).collect();
+        let mut query = Query::new();
+
+        query.pages()
+            .titles(titles.join("|"))
+            .titles("Page%200")
+            .info();
+
+        let mut batches = PageBatches::new(&query);
+
+        assert_eq!(batches.inputs().len(), 1200);
+        assert_eq!(batches.queries().len(), 24);
+
+        batches.high_limits();
+
+        let mut queries = batches.queries();
+
+        assert_eq!(batches.limit(), Limit::Value(MAX_TITLES_HIGH_LIMITS));
+
+        assert_eq!(queries.len(), 3);
+        assert_query_contains(&mut queries[1], &["titles=Page%20500|Page%20501|", "prop=info"]);
+
+        query.pages()
+            .extracts()
+            .ex_limit("max");
+
+        assert_eq!(PageBatches::new(&query).batch_size(), MAX_EXTRACTS);
+
+        let mut ids = Query::new();
+
+        ids.pages()
+            .page_ids("1|2|3")
+            .extracts()
+            .ex_limit("2");
+
+        let batches = PageBatches::new(&ids);
+
+        assert!(batches.is_page_ids());
+        assert_eq!(batches.queries().len(), 2);
+
+        let bot = Rights::new(vec!["read", "apihighlimits"]);
+        let mut batches = PageBatches::new(&query);
+
+        batches.chunk_size(Limit::Max)
+            .rights(&bot);
+
+        assert_eq!(batches.batch_size(), MAX_EXTRACTS);
+
+        let mut titles = Query::new();
+
+        titles.pages()
+            .titles("Death")
+            .info();
+
+        let mut batches = PageBatches::new(&titles);
+
+        batches.chunk_size(Limit::Max);
+
+        assert_eq!(batches.batch_size(), MAX_TITLES);
+
+        batches.rights(&bot);
+
+        assert_eq!(batches.batch_size(), MAX_TITLES_HIGH_LIMITS);
+    }
+
+    #[test]
+    fn maps_inputs_through_normalized_titles_and_redirects()
+    {
+        let mut query = Query::new();
+
+        query.pages()
+            .titles("death|Main%20page|Nonexistent page xyz|Unsent")
+            .redirects();
+
+        let batches = PageBatches::new(&query);
+        let first = response("{\"batchcomplete\":true,\"query\":{\"normalized\":[{\"fromencoded\":false,\"from\":\"death\",\"to\":\"Death\"}],\"redirects\":[{\"from\":\"Main page\",\"to\":\"Main Page\",\"tofragment\":\"Top\"}],\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221},{\"ns\":0,\"title\":\"Main Page\",\"pageid\":15580374}]}}");
+        let second = response("{\"batchcomplete\":true,\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true},{\"ns\":0,\"title\":\"Death\",\"pageid\":8221}]}}");
+
+        let pages = batches.merge(vec![first, second]);
+        let by_input: Vec<(&str, Option<&str>)> = pages.by_input()
+            .into_iter()
+            .map(|(input, page)| (input, page.map(|page| page.title.as_str())))
+            .collect();
+
+        assert_eq!(pages.pages.len(), 3);
+        assert_eq!(pages.redirects[0].to_fragment.as_deref(), Some("Top"));
+        assert_eq!(by_input, vec![
+            ("death", Some("Death")),
+            ("Main%20page", Some("Main Page")),
+            ("Nonexistent page xyz", Some("Nonexistent page xyz")),
+            ("Unsent", None),
+        ]);
+    }
+}
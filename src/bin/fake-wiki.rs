@@ -0,0 +1,65 @@
+//! Serves a fake wiki over http.
+//!
+//! ```text
+//! fake-wiki [fixture.json] [address]
+//! ```
+//!
+//! Without a fixture the wiki is empty. The address defaults to
+//! `127.0.0.1:8080`, so queries are sent to `http://127.0.0.1:8080/w/api.php`.
+
+use http::Request;
+use tiny_http::{Header, Response, Server};
+
+use std::env;
+use std::process;
+
+use wikiquery::fake::FakeWiki;
+
+fn main()
+{
+    let mut args = env::args().skip(1);
+    let wiki = match args.next()
+    {
+        Some(path) => FakeWiki::from_fixture(&path).unwrap_or_else(|err| {
+            eprintln!("couldn't read {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => FakeWiki::new(),
+    };
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+
+    let server = Server::http(&address).unwrap_or_else(|err| {
+        eprintln!("couldn't listen on {}: {}", address, err);
+        process::exit(1);
+    });
+
+    eprintln!("serving {} pages on http://{}/w/api.php", wiki.pages().len(), address);
+
+    for request in server.incoming_requests()
+    {
+        let response = match Request::get(request.url()).body(())
+        {
+            Ok(req) => wiki.handle(&req),
+            Err(_) => {
+                let _ = request.respond(Response::from_string("Bad Request").with_status_code(400));
+                continue;
+            },
+        };
+
+        let mut reply = Response::from_data(response.body().clone())
+            .with_status_code(response.status().as_u16());
+
+        for (name, value) in response.headers()
+        {
+            if let Ok(header) = Header::from_bytes(name.as_str().as_bytes(), value.as_bytes())
+            {
+                reply.add_header(header);
+            }
+        }
+
+        if let Err(err) = request.respond(reply)
+        {
+            eprintln!("couldn't respond: {}", err);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Serves a caching proxy of a wiki's `api.php`.
+//!
+//! ```text
+//! wikiquery-proxy [options]
+//!
+//!     --listen ADDRESS    where to serve, defaults to 127.0.0.1:8081
+//!     --upstream URL      the wiki to forward to, defaults to https://en.wikipedia.org
+//!     --contact CONTACT   a url or email the wiki can reach the team at
+//!     --cache-dir DIR     keeps responses in DIR rather than in memory
+//!     --cache-size N      how many responses are kept in memory, defaults to 10000
+//!     --ttl SECONDS       how long responses stay fresh, defaults to 3600
+//!     --maxlag SECONDS    sent with requests that don't set it, defaults to 5
+//!     --rate REQUESTS     the most requests sent upstream per second, defaults to 10
+//!     --workers N         how many requests are handled at once, defaults to 16
+//! ```
+//!
+//! `GET /w/api.php` requests are forwarded upstream with a client that caches
+//! responses, sends identical requests in flight only once, sends `maxlag`,
+//! retries transient failures and limits its rate, so every service behind
+//! the proxy presents as one well-behaved api consumer. Requests are forwarded
+//! as they were asked for, so a `limit` of `max` is left for the wiki to
+//! resolve, and the wiki's last response is answered with its status, headers
+//! and body. `/stats` shows the cache hits and misses and the client's
+//! counters as json, and `/metrics` shows them in the Prometheus text format.
+
+use serde_json::json;
+use tiny_http::{Header, Method, Response, Server};
+
+use std::env;
+use std::io::Cursor;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use wikiquery::client::Client;
+use wikiquery::transport::blocking::Blocking;
+use wikiquery::transport::cache::{Cached, DiskStore, MemoryStore, Store};
+use wikiquery::transport::throttle::Throttled;
+use wikiquery::transport::Transport;
+use wikiquery::user_agent::UserAgent;
+
+const USAGE: &str = "usage: wikiquery-proxy [--listen ADDRESS] [--upstream URL] [--contact CONTACT] \
+                     [--cache-dir DIR] [--cache-size N] [--ttl SECONDS] [--maxlag SECONDS] [--rate REQUESTS] \
+                     [--workers N]";
+
+/// The prefix of the exported metrics.
+const METRICS_PREFIX: &str = "wikiquery_proxy";
+
+/// Upstream headers that describe the connection or an encoding the client
+/// already undid, so they aren't forwarded.
+const DROPPED_HEADERS: &[&str] = &["connection", "content-encoding", "content-length", "keep-alive", "transfer-encoding"];
+
+type ProxyClient<T, S> = Client<Cached<T, S>>;
+
+/// An answer to a request, turned into a response once it's sent.
+#[derive(Debug)]
+struct Reply
+{
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply
+{
+    fn new(status: u16, body: Vec<u8>, content_type: &str) -> Reply
+    {
+        Reply {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body,
+        }
+    }
+
+    fn text(status: u16, body: &str) -> Reply
+    {
+        Reply::new(status, body.as_bytes().to_vec(), "text/plain; charset=utf-8")
+    }
+
+    /// Answers with an upstream response, leaving out the [`DROPPED_HEADERS`].
+    ///
+    /// [`DROPPED_HEADERS`]: constant.DROPPED_HEADERS.html
+    fn upstream(response: http::Response<Vec<u8>>) -> Reply
+    {
+        let headers = response.headers().iter()
+            .filter(|(name, _)| !DROPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+
+        Reply {
+            status: response.status().as_u16(),
+            headers,
+            body: response.into_body(),
+        }
+    }
+
+    fn into_response(self) -> Response<Cursor<Vec<u8>>>
+    {
+        let mut response = Response::from_data(self.body).with_status_code(self.status);
+
+        for (name, value) in self.headers
+        {
+            if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes())
+            {
+                response.add_header(header);
+            }
+        }
+
+        response
+    }
+}
+
+#[derive(Debug)]
+struct Options
+{
+    listen: String,
+    upstream: String,
+    contact: Option<String>,
+    cache_dir: Option<String>,
+    cache_size: usize,
+    ttl: u64,
+    maxlag: String,
+    rate: f64,
+    workers: usize,
+}
+
+impl Options
+{
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String>
+    {
+        let mut options = Options {
+            listen: "127.0.0.1:8081".to_string(),
+            upstream: "https://en.wikipedia.org".to_string(),
+            contact: None,
+            cache_dir: None,
+            cache_size: 10_000,
+            ttl: 3600,
+            maxlag: "5".to_string(),
+            rate: 10.0,
+            workers: 16,
+        };
+
+        while let Some(name) = args.next()
+        {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", name))?;
+            let number = |value: &str| value.parse::<u64>().map_err(|_| format!("{} needs a number, not {}", name, value));
+
+            match name.as_str()
+            {
+                "--listen" => options.listen = value,
+                "--upstream" => options.upstream = value.trim_end_matches('/').to_string(),
+                "--contact" => options.contact = Some(value),
+                "--cache-dir" => options.cache_dir = Some(value),
+                "--cache-size" => options.cache_size = number(&value)? as usize,
+                "--ttl" => options.ttl = number(&value)?,
+                "--maxlag" => options.maxlag = number(&value)?.to_string(),
+                "--rate" => options.rate = value.parse().map_err(|_| format!("{} needs a number, not {}", name, value))?,
+                "--workers" => options.workers = (number(&value)? as usize).max(1),
+                _ => return Err(format!("unknown option {}", name)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn main()
+{
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    match &options.cache_dir
+    {
+        Some(dir) => {
+            let store = DiskStore::new(dir).unwrap_or_else(|err| {
+                eprintln!("couldn't use {} as the cache: {}", dir, err);
+                process::exit(1);
+            });
+
+            serve(store, &options)
+        },
+        None => serve(MemoryStore::new(options.cache_size), &options),
+    }
+}
+
+fn serve<S: Store + Send + Sync + 'static>(store: S, options: &Options)
+{
+    let mut cached = Cached::new(Throttled::per_second(Blocking::new(), options.rate), store);
+
+    cached.ttl(Duration::from_secs(options.ttl));
+
+    let contact = options.contact.as_deref().unwrap_or("https://github.com/dastardlychimp/wikiquery");
+    let mut client = Client::new(cached);
+
+    client.maxlag(options.maxlag.as_str())
+        .resolve_limits(false)
+        .user_agent(UserAgent::new("wikiquery-proxy", env!("CARGO_PKG_VERSION"), contact));
+
+    let client = Arc::new(client);
+    let server = Server::http(&options.listen).unwrap_or_else(|err| {
+        eprintln!("couldn't listen on {}: {}", options.listen, err);
+        process::exit(1);
+    });
+    let server = Arc::new(server);
+
+    eprintln!("forwarding http://{}/w/api.php to {}", options.listen, options.upstream);
+
+    // Identical requests in flight are coalesced, so a worker may wait on
+    // another's request, but never on more than the pool holds.
+    let workers: Vec<_> = (0..options.workers)
+        .map(|_| {
+            let client = client.clone();
+            let server = server.clone();
+            let upstream = options.upstream.clone();
+
+            thread::spawn(move || work(&client, &server, &upstream))
+        })
+        .collect();
+
+    for worker in workers
+    {
+        let _ = worker.join();
+    }
+}
+
+/// Answers requests until the server stops.
+fn work<T: Transport, S: Store>(client: &ProxyClient<T, S>, server: &Server, upstream: &str)
+{
+    loop
+    {
+        let request = match server.recv()
+        {
+            Ok(request) => request,
+            Err(err) => {
+                eprintln!("couldn't receive a request: {}", err);
+                return;
+            },
+        };
+        let reply = handle(client, upstream, request.method(), request.url());
+
+        if let Err(err) = request.respond(reply.into_response())
+        {
+            eprintln!("couldn't respond: {}", err);
+        }
+    }
+}
+
+fn handle<T: Transport, S: Store>(client: &ProxyClient<T, S>, upstream: &str, method: &Method, url: &str) -> Reply
+{
+    if *method != Method::Get
+    {
+        return Reply::text(405, "Only GET requests are forwarded");
+    }
+
+    match url.split('?').next().unwrap_or("")
+    {
+        "/w/api.php" => forward(client, upstream, url),
+        "/stats" => stats(client),
+        "/metrics" => metrics(client),
+        _ => Reply::text(404, "Not Found"),
+    }
+}
+
+/// Sends the request upstream as it was asked for and answers with the last
+/// response of the wiki, or `502 Bad Gateway` when none was received.
+fn forward<T: Transport, S: Store>(client: &ProxyClient<T, S>, upstream: &str, url: &str) -> Reply
+{
+    let request = match http::Request::get(format!("{}{}", upstream, url).as_str()).body(())
+    {
+        Ok(request) => request,
+        Err(_) => return Reply::text(400, "Bad Request"),
+    };
+
+    match client.send_response(request)
+    {
+        Ok(response) => Reply::upstream(response),
+        Err(err) => Reply::text(502, &err.to_string()),
+    }
+}
+
+fn stats<T: Transport, S: Store>(client: &ProxyClient<T, S>) -> Reply
+{
+    let cache = client.transport().stats();
+    let body = json!({
+        "cache": cache,
+        "hit_ratio": cache.hit_ratio(),
+        "client": client.metrics().snapshot(),
+    });
+
+    Reply::new(200, body.to_string().into_bytes(), "application/json; charset=utf-8")
+}
+
+fn metrics<T: Transport, S: Store>(client: &ProxyClient<T, S>) -> Reply
+{
+    let body = format!(
+        "{}{}",
+        client.metrics().snapshot().to_prometheus(METRICS_PREFIX),
+        client.transport().stats().to_prometheus(METRICS_PREFIX)
+    );
+
+    Reply::new(200, body.into_bytes(), "text/plain; version=0.0.4")
+}
+
+#[cfg(test)]
+mod proxy_tests
+{
+    use super::*;
+
+    use wikiquery::error::Error;
+    use wikiquery::retry::RetryPolicy;
+
+    use std::cell::RefCell;
+    use std::io;
+
+    const EMPTY: &str = "{\"batchcomplete\":true,\"query\":{\"categorymembers\":[]}}";
+    const LAGGED: &str = "<?xml version=\"1.0\"?><api servedby=\"mw1\"><error code=\"maxlag\" info=\"Waiting for 10.64.0.1: 7 seconds lagged.\" /></api>";
+    const UPSTREAM: &str = "https://en.wikipedia.org";
+
+    fn respond(status: u16, headers: &[(&str, &str)], body: &str) -> Result<http::Response<Vec<u8>>, Error>
+    {
+        let mut builder = http::Response::builder();
+
+        builder.status(status);
+
+        for (name, value) in headers
+        {
+            builder.header(*name, *value);
+        }
+
+        Ok(builder.body(body.as_bytes().to_vec()).unwrap())
+    }
+
+    fn proxy_client<T: Transport>(transport: T) -> ProxyClient<T, MemoryStore>
+    {
+        let mut client = Client::new(Cached::new(transport, MemoryStore::new(10)));
+
+        client.resolve_limits(false)
+            .retry(RetryPolicy::none());
+        client
+    }
+
+    fn header<'a>(reply: &'a Reply, name: &str) -> Option<&'a str>
+    {
+        reply.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String>
+    {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn forwards_requests_as_they_were_asked_for()
+    {
+        let sent = RefCell::new(Vec::new());
+        let transport = |request: http::Request<()>| {
+            sent.borrow_mut().push(request.uri().to_string());
+            respond(200, &[("Content-Type", "application/json; charset=utf-8")], EMPTY)
+        };
+        let client = proxy_client(transport);
+
+        let url = "/w/api.php?action=query&format=json&list=categorymembers&cmtitle=Category:Death&cmlimit=max";
+        let reply = handle(&client, UPSTREAM, &Method::Get, url);
+        let sent = sent.borrow();
+
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, EMPTY.as_bytes());
+        assert_eq!(header(&reply, "Content-Type"), Some("application/json; charset=utf-8"));
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("https://en.wikipedia.org/w/api.php?"));
+        assert!(sent[0].split('&').any(|param| param == "cmlimit=max"));
+    }
+
+    #[test]
+    fn answers_api_errors_as_the_wiki_sent_them()
+    {
+        let headers = [
+            ("Content-Type", "text/xml; charset=utf-8"),
+            ("MediaWiki-API-Error", "maxlag"),
+            ("Retry-After", "5"),
+            ("Content-Encoding", "gzip"),
+            ("Content-Length", "12"),
+        ];
+        let client = proxy_client(|_request| respond(200, &headers, LAGGED));
+        let reply = handle(&client, UPSTREAM, &Method::Get, "/w/api.php?action=query&format=xml&maxlag=5");
+
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, LAGGED.as_bytes());
+        assert_eq!(header(&reply, "Content-Type"), Some("text/xml; charset=utf-8"));
+        assert_eq!(header(&reply, "MediaWiki-API-Error"), Some("maxlag"));
+        assert_eq!(header(&reply, "Retry-After"), Some("5"));
+        assert_eq!(header(&reply, "Content-Encoding"), None);
+        assert_eq!(header(&reply, "Content-Length"), None);
+    }
+
+    #[test]
+    fn passes_unsuccessful_statuses_through()
+    {
+        let client = proxy_client(|_request| respond(503, &[("Content-Type", "text/html; charset=utf-8")], "<p>Service Unavailable</p>"));
+        let reply = handle(&client, UPSTREAM, &Method::Get, "/w/api.php?action=query&format=json");
+
+        assert_eq!(reply.status, 503);
+        assert_eq!(reply.body, b"<p>Service Unavailable</p>");
+        assert_eq!(header(&reply, "Content-Type"), Some("text/html; charset=utf-8"));
+    }
+
+    #[test]
+    fn failures_to_send_are_bad_gateways()
+    {
+        let client = proxy_client(|_request| Err(Error::Transport(Box::new(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")))));
+        let reply = handle(&client, UPSTREAM, &Method::Get, "/w/api.php?action=query&format=json");
+
+        assert_eq!(reply.status, 502);
+        assert_eq!(header(&reply, "Content-Type"), Some("text/plain; charset=utf-8"));
+    }
+
+    #[test]
+    fn routes_requests()
+    {
+        let client = proxy_client(|_request| respond(200, &[], EMPTY));
+
+        assert_eq!(handle(&client, UPSTREAM, &Method::Post, "/w/api.php").status, 405);
+        assert_eq!(handle(&client, UPSTREAM, &Method::Get, "/wiki/Death").status, 404);
+
+        let stats = handle(&client, UPSTREAM, &Method::Get, "/stats");
+        let body: serde_json::Value = serde_json::from_slice(&stats.body).unwrap();
+
+        assert_eq!(stats.status, 200);
+        assert!(body.get("cache").is_some());
+        assert!(body.get("client").is_some());
+
+        let metrics = handle(&client, UPSTREAM, &Method::Get, "/metrics");
+
+        assert_eq!(metrics.status, 200);
+        assert_eq!(header(&metrics, "Content-Type"), Some("text/plain; version=0.0.4"));
+        assert!(String::from_utf8(metrics.body).unwrap().contains("wikiquery_proxy_"));
+    }
+
+    #[test]
+    fn parses_options()
+    {
+        let options = Options::parse(args(&[])).unwrap();
+
+        assert_eq!(options.listen, "127.0.0.1:8081");
+        assert_eq!(options.workers, 16);
+        assert!(options.cache_dir.is_none());
+
+        let options = Options::parse(args(&[
+            "--upstream", "https://de.wikipedia.org/",
+            "--workers", "4",
+            "--rate", "2.5",
+            "--maxlag", "3",
+        ])).unwrap();
+
+        assert_eq!(options.upstream, "https://de.wikipedia.org");
+        assert_eq!(options.workers, 4);
+        assert_eq!(options.rate, 2.5);
+        assert_eq!(options.maxlag, "3");
+        assert_eq!(Options::parse(args(&["--workers", "0"])).unwrap().workers, 1);
+    }
+
+    #[test]
+    fn rejects_bad_options()
+    {
+        assert_eq!(Options::parse(args(&["--ttl", "soon"])).unwrap_err(), "--ttl needs a number, not soon");
+        assert_eq!(Options::parse(args(&["--cache-dir"])).unwrap_err(), "--cache-dir needs a value");
+        assert_eq!(Options::parse(args(&["--verbose", "1"])).unwrap_err(), "unknown option --verbose");
+    }
+}
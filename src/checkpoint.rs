@@ -0,0 +1,265 @@
+//! Saves the progress of a long crawl so it can be resumed after a crash.
+//!
+//! A [`Checkpoint`] holds the params of a query, its last continue block and
+//! counters of what has been fetched. A [`Checkpointer`] writes one to disk
+//! every few responses, replacing the previous file atomically so a crash
+//! mid-write never leaves a corrupt checkpoint behind.
+//!
+//! Items handled after the last save are fetched again when resuming, so
+//! processing should tolerate seeing an item twice.
+//!
+//! # Examples
+//! ```no_run
+//! use wikiquery::requests::Query;
+//! use wikiquery::pagination::Paginator;
+//! use wikiquery::checkpoint::Checkpointer;
+//!
+//! let mut checkpointer = Checkpointer::new("all_categories.checkpoint", 10);
+//!
+//! let mut paginator = match checkpointer.load().unwrap()
+//! {
+//!     Some(checkpoint) => Paginator::resume(checkpoint),
+//!     None => {
+//!         let mut query = Query::new();
+//!         query.all_categories().ac_limit("500");
+//!         Paginator::new(query)
+//!     },
+//! };
+//!
+//! while let Some(request) = paginator.next_request()
+//! {
+//!     /*
+//!         Send the request and read the body
+//!     */
+//!     # let body = vec![];
+//!     let response = paginator.handle_response(&body).unwrap();
+//!
+//!     checkpointer.record(&paginator).unwrap();
+//! }
+//! ```
+//!
+//! [`Checkpoint`]: struct.Checkpoint.html
+//! [`Checkpointer`]: struct.Checkpointer.html
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::pagination::Paginator;
+use crate::responses::ContinueBlock;
+
+/// The saved state of a paginated query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint
+{
+    /// The params of the query, without continuation.
+    pub params: BTreeMap<String, String>,
+    /// The continue block of the last response handled.
+    pub continue_block: Option<ContinueBlock>,
+    /// The number of responses handled.
+    pub responses: u64,
+    /// The number of items received.
+    pub items: u64,
+    /// Whether the last response completed its batch.
+    pub batch_complete: bool,
+}
+
+impl Checkpoint
+{
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint>
+    {
+        let bytes = fs::read(path)?;
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Writes the checkpoint to `path`, replacing any previous checkpoint.
+    ///
+    /// The checkpoint is written to a temporary file next to `path` first,
+    /// then renamed over it.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()>
+    {
+        let path = path.as_ref();
+        let mut tmp_name = path.file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "checkpoint path has no file name"))?
+            .to_os_string();
+
+        tmp_name.push(".tmp");
+
+        let tmp_path = path.with_file_name(tmp_name);
+        let mut file = File::create(&tmp_path)?;
+
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        file.sync_all()?;
+
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Whether the query had no continuation left.
+    pub fn is_finished(&self) -> bool
+    {
+        self.responses > 0 && self.continue_block.is_none()
+    }
+}
+
+/// Saves a checkpoint of a paginator every `every` responses.
+pub struct Checkpointer
+{
+    path: PathBuf,
+    every: u64,
+    saved_at: Option<u64>,
+}
+
+impl Checkpointer
+{
+    pub fn new<P: Into<PathBuf>>(path: P, every: u64) -> Checkpointer
+    {
+        Checkpointer {
+            path: path.into(),
+            every: every.max(1),
+            saved_at: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Loads the saved checkpoint, if there is one.
+    pub fn load(&mut self) -> io::Result<Option<Checkpoint>>
+    {
+        match Checkpoint::load(&self.path)
+        {
+            Ok(checkpoint) => {
+                self.saved_at = Some(checkpoint.responses);
+                Ok(Some(checkpoint))
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Saves a checkpoint if `every` responses were handled since the last one,
+    /// or if the paginator is done.
+    ///
+    /// Returns whether a checkpoint was saved.
+    pub fn record(&mut self, paginator: &Paginator) -> io::Result<bool>
+    {
+        let responses = paginator.responses();
+        let due = match self.saved_at
+        {
+            Some(saved_at) => responses >= saved_at + self.every,
+            None => responses >= self.every,
+        };
+
+        if (due || paginator.is_done()) && self.saved_at != Some(responses)
+        {
+            paginator.checkpoint().save(&self.path)?;
+            self.saved_at = Some(responses);
+
+            Ok(true)
+        }
+        else
+        {
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests
+{
+    use super::*;
+    use crate::requests::Query;
+
+    fn temp_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("wikiquery-{}-{}.checkpoint", name, std::process::id()))
+    }
+
+    fn body(cont: &str) -> String
+    {
+        format!("{{\"continue\":{{\"sroffset\":{},\"accontinue\":\"{}\",\"continue\":\"-||\"}},\"query\":{{\"allcategories\":[{{\"category\":\"A\"}},{{\"category\":\"B\"}}]}}}}", cont.len(), cont)
+    }
+
+    fn next_query(paginator: &mut Paginator) -> Vec<String>
+    {
+        let request = paginator.next_request().unwrap().unwrap();
+        let mut params: Vec<String> = request.uri().query().unwrap()
+            .split('&')
+            .map(String::from)
+            .collect();
+
+        params.sort();
+        params
+    }
+
+    #[test]
+    fn saves_every_n_responses_and_resumes()
+    {
+        let path = temp_path("resume");
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_limit("2");
+
+        let mut paginator = Paginator::new(query);
+        let mut checkpointer = Checkpointer::new(&path, 2);
+
+        paginator.handle_response(body("B").as_bytes()).unwrap();
+        assert!(!checkpointer.record(&paginator).unwrap());
+
+        paginator.handle_response(body("Cats").as_bytes()).unwrap();
+        assert!(checkpointer.record(&paginator).unwrap());
+        assert!(!checkpointer.record(&paginator).unwrap());
+
+        let checkpoint = Checkpointer::new(&path, 2).load().unwrap().unwrap();
+        let mut resumed = Paginator::resume(checkpoint.clone());
+
+        assert_eq!(checkpoint, paginator.checkpoint());
+        assert_eq!(checkpoint.items, 4);
+        assert_eq!(resumed.items(), 4);
+        assert_eq!(next_query(&mut resumed), next_query(&mut paginator));
+
+        // Continue params that don't end in "continue" are still cleared.
+        resumed.handle_response(b"{\"batchcomplete\":true,\"continue\":{\"accontinue\":\"D\",\"continue\":\"-||\"},\"query\":{}}").unwrap();
+        assert!(next_query(&mut resumed).iter().all(|param| !param.starts_with("sroffset")));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saves_when_done()
+    {
+        let path = temp_path("done");
+        let mut query = Query::new();
+
+        query.all_categories();
+
+        let mut paginator = Paginator::new(query);
+        let mut checkpointer = Checkpointer::new(&path, 100);
+
+        paginator.handle_response(b"{\"batchcomplete\":true,\"query\":{\"allcategories\":[]}}").unwrap();
+
+        assert!(checkpointer.record(&paginator).unwrap());
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+
+        assert!(checkpoint.is_finished());
+        assert!(Paginator::resume(checkpoint).next_request().is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_checkpoint_loads_nothing()
+    {
+        let mut checkpointer = Checkpointer::new(temp_path("missing"), 1);
+
+        assert!(checkpointer.load().unwrap().is_none());
+    }
+}
@@ -0,0 +1,901 @@
+//! Runs queries with a [`Transport`] and parses their responses.
+//!
+//! # Examples
+//! ```
+//! use http::Response;
+//! use wikiquery::client::Client;
+//! use wikiquery::requests::Query;
+//!
+//! # let transport = |_request| {
+//! #     Ok(Response::new(br#"{"batchcomplete":true,"query":{"allcategories":[{"category":"Lists"}]}}"#.to_vec()))
+//! # };
+//! let client = Client::new(transport);
+//! let mut query = Query::new();
+//!
+//! query.all_categories()
+//!     .ac_limit("500");
+//!
+//! for response in client.paginate(query)
+//! {
+//!     for category in response.unwrap().query.all_categories.unwrap_or_default()
+//!     {
+//!         println!("{}", category.category);
+//!     }
+//! }
+//! ```
+//!
+//! [`Transport`]: ../transport/trait.Transport.html
+
+use http::{Request, Response};
+
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::batch::{PageBatches, Pages};
+use crate::error::{Error, WikiError};
+use crate::metrics::Metrics;
+use crate::pagination::Paginator;
+use crate::requests::{Limit, Query};
+use crate::responses;
+use crate::retry::RetryPolicy;
+use crate::rights::{self, Rights};
+use crate::transport::Transport;
+use crate::user_agent::UserAgent;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "async")]
+pub mod executor;
+
+/// Executes queries with a transport.
+#[derive(Debug, Clone)]
+pub struct Client<T>
+{
+    transport: T,
+    maxlag: Option<String>,
+    retry: RetryPolicy,
+    sleep: fn(Duration),
+    user_agent: UserAgent,
+    metrics: Arc<Metrics>,
+    rights: Arc<OnceLock<Rights>>,
+    resolve_limits: bool,
+}
+
+impl<T: Default + Transport> Default for Client<T>
+{
+    fn default() -> Client<T>
+    {
+        Client::new(T::default())
+    }
+}
+
+impl<T: Transport> Client<T>
+{
+    pub fn new(transport: T) -> Client<T>
+    {
+        Client {
+            transport,
+            maxlag: None,
+            retry: RetryPolicy::new(),
+            sleep: thread::sleep,
+            user_agent: UserAgent::default(),
+            metrics: Arc::new(Metrics::new()),
+            rights: Arc::new(OnceLock::new()),
+            resolve_limits: true,
+        }
+    }
+
+    /// Sends `maxlag` with every request that doesn't set it already.
+    ///
+    /// See [`Query::maxlag`].
+    ///
+    /// [`Query::maxlag`]: ../requests/struct.Query.html#method.maxlag
+    pub fn maxlag<S: Into<String>>(&mut self, seconds: S) -> &mut Self
+    {
+        self.maxlag = Some(seconds.into());
+        self
+    }
+
+    /// Which failed requests are retried and how long to wait first.
+    ///
+    /// Defaults to [`RetryPolicy::new`].
+    ///
+    /// [`RetryPolicy::new`]: ../retry/struct.RetryPolicy.html#method.new
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut Self
+    {
+        self.retry = policy;
+        self
+    }
+
+    /// Identifies the application in every request, see [`UserAgent`].
+    ///
+    /// Defaults to the crate's own agent.
+    ///
+    /// [`UserAgent`]: ../user_agent/struct.UserAgent.html
+    pub fn user_agent(&mut self, agent: UserAgent) -> &mut Self
+    {
+        self.user_agent = agent;
+        self
+    }
+
+    /// Replaces [`thread::sleep`] for waiting between retries, for example
+    /// to not wait in tests.
+    ///
+    /// [`thread::sleep`]: https://doc.rust-lang.org/std/thread/fn.sleep.html
+    pub fn sleep_with(&mut self, sleep: fn(Duration)) -> &mut Self
+    {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Counts the requests, retries and warnings of this client.
+    pub fn metrics(&self) -> &Metrics
+    {
+        &self.metrics
+    }
+
+    /// Counts into the given metrics instead, for example to count every
+    /// client of a job together.
+    pub fn share_metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self
+    {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The rights of the account requests are sent as.
+    ///
+    /// They're fetched with [`Rights::query`] the first time they're needed
+    /// and kept for the life of the client and its clones.
+    ///
+    /// [`Rights::query`]: ../rights/struct.Rights.html#method.query
+    pub fn rights(&self) -> Result<&Rights, Error>
+    {
+        self.rights_for(None)
+    }
+
+    /// The rights, fetched from the wiki of `like` if it's given.
+    fn rights_for(&self, like: Option<&Request<()>>) -> Result<&Rights, Error>
+    {
+        if let Some(rights) = self.rights.get()
+        {
+            return Ok(rights);
+        }
+
+        let request = match like
+        {
+            Some(like) => rights::request_like(like)?,
+            None => Rights::query().build()?,
+        };
+        let body = self.send(request)?;
+        let response = record_parsed(&self.metrics, responses::parse(&body, false))?;
+
+        Ok(self.rights.get_or_init(|| Rights::from_response(&response)))
+    }
+
+    /// Uses the given rights rather than fetching them, for example when
+    /// they're known already.
+    pub fn assume_rights(&mut self, rights: Rights) -> &mut Self
+    {
+        self.rights = Arc::new(OnceLock::from(rights));
+        self
+    }
+
+    /// Whether limits of `max` are replaced with the most the account may ask
+    /// for before they're sent, which is the default.
+    ///
+    /// Without it, requests are sent as they were given, for example to
+    /// forward them on behalf of another client.
+    pub fn resolve_limits(&mut self, resolve: bool) -> &mut Self
+    {
+        self.resolve_limits = resolve;
+        self
+    }
+
+    pub fn transport(&self) -> &T
+    {
+        &self.transport
+    }
+
+    pub fn into_transport(self) -> T
+    {
+        self.transport
+    }
+
+    /// Sends a request and returns the body of a successful response.
+    ///
+    /// An unsuccessful status is returned as [`Error::Status`], unless the body
+    /// holds an api error. Transient failures are retried, see
+    /// [`Client::retry`]. Limits of `max` are replaced with the most the
+    /// account may ask for, see [`Client::rights`] and
+    /// [`Client::resolve_limits`].
+    ///
+    /// [`Error::Status`]: ../error/enum.Error.html#variant.Status
+    /// [`Client::retry`]: struct.Client.html#method.retry
+    /// [`Client::rights`]: struct.Client.html#method.rights
+    /// [`Client::resolve_limits`]: struct.Client.html#method.resolve_limits
+    pub fn send(&self, request: Request<()>) -> Result<Vec<u8>, Error>
+    {
+        match self.send_with_retries(request)?
+        {
+            (_, Some(err)) => Err(err),
+            (response, None) => Ok(response.into_body()),
+        }
+    }
+
+    /// Sends a request like [`Client::send`], but returns the last response
+    /// as it was received, whatever its status or the error it holds.
+    ///
+    /// Only failures to send the request are errors, for example to forward
+    /// the response to another client.
+    ///
+    /// [`Client::send`]: struct.Client.html#method.send
+    pub fn send_response(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        self.send_with_retries(request).map(|(response, _)| response)
+    }
+
+    /// Sends a request until it succeeds or isn't retried, and returns the
+    /// last response with the error it holds.
+    fn send_with_retries(&self, request: Request<()>) -> Result<(Response<Vec<u8>>, Option<Error>), Error>
+    {
+        let mut request = with_maxlag(request, self.maxlag.as_ref())?;
+
+        if self.resolve_limits && rights::asks_for_max(&request)
+        {
+            self.rights_for(Some(&request))?.resolve(&mut request)?;
+        }
+
+        enter_span!("send", modules = %crate::trace::modules(&request), params = crate::trace::param_count(&request));
+
+        self.user_agent.apply(&mut request)?;
+        let mut attempt = 0;
+        let mut waited = Duration::from_secs(0);
+
+        loop
+        {
+            let started = Instant::now();
+            let (response, err, retry_after) = match self.transport.send(copy_request(&request)?)
+            {
+                Ok(response) => {
+                    observe(&self.metrics, started, Some(&response));
+
+                    match response_error(&response)
+                    {
+                        Some(err) => {
+                            let retry_after = retry_after(&response);
+
+                            (Some(response), err, retry_after)
+                        },
+                        None => return Ok((response, None)),
+                    }
+                },
+                Err(err) => {
+                    observe(&self.metrics, started, None);
+                    (None, err, None)
+                },
+            };
+
+            match self.retry.next_delay(&err, attempt, retry_after, waited)
+            {
+                Some(delay) => {
+                    self.metrics.record_retry();
+                    (self.sleep)(delay);
+                    attempt += 1;
+                    waited += delay;
+                },
+                None => {
+                    self.metrics.record_error();
+
+                    return match response
+                    {
+                        Some(response) => Ok((response, Some(err))),
+                        None => Err(err),
+                    };
+                },
+            }
+        }
+    }
+
+    /// Sends a single request for the query and parses the response.
+    ///
+    /// The query isn't continued.
+    pub fn execute(&self, query: &mut Query) -> Result<responses::Query, Error>
+    {
+        let body = self.send(query.build()?)?;
+
+        record_parsed(&self.metrics, responses::parse(&body, query.is_legacy_format()))
+    }
+
+    /// Runs every batch of a pages query through all of its continuations and
+    /// merges their pages.
+    ///
+    /// See [`PageBatches`].
+    ///
+    /// A chunk size of [`Limit::Max`] follows the rights of the account, see
+    /// [`Client::rights`].
+    ///
+    /// [`PageBatches`]: ../batch/struct.PageBatches.html
+    /// [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+    /// [`Client::rights`]: struct.Client.html#method.rights
+    pub fn execute_pages(&self, batches: &PageBatches) -> Result<Pages, Error>
+    {
+        let mut responses = Vec::new();
+        let mut batches = batches.clone();
+
+        if batches.limit() == Limit::Max
+        {
+            batches.rights(self.rights()?);
+        }
+
+        for query in batches.queries()
+        {
+            for response in self.paginate(query)
+            {
+                responses.push(response?);
+            }
+        }
+
+        Ok(batches.merge(responses))
+    }
+
+    /// Runs the query through all of its continuations.
+    ///
+    /// Responses are fetched lazily as the iterator is advanced.
+    pub fn paginate<'a>(&self, query: Query<'a>) -> Responses<'_, 'a, T>
+    {
+        self.resume(Paginator::new(query))
+    }
+
+    /// Continues a paginator, for example one resumed from a checkpoint.
+    pub fn resume<'a>(&self, paginator: Paginator<'a>) -> Responses<'_, 'a, T>
+    {
+        Responses {
+            client: self,
+            paginator,
+            failed: false,
+        }
+    }
+}
+
+/// Adds `maxlag` to the request, unless it's already set.
+pub(crate) fn with_maxlag(request: Request<()>, maxlag: Option<&String>) -> Result<Request<()>, Error>
+{
+    let maxlag = match maxlag
+    {
+        Some(maxlag) => maxlag,
+        None => return Ok(request),
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let separator = match parts.uri.query()
+    {
+        Some(query) if query.split('&').any(|param| param.starts_with("maxlag=")) => {
+            return Ok(Request::from_parts(parts, body));
+        },
+        Some(_) => "&",
+        None => "?",
+    };
+
+    parts.uri = format!("{}{}maxlag={}", parts.uri, separator, maxlag)
+        .parse()
+        .map_err(http::Error::from)?;
+
+    Ok(Request::from_parts(parts, body))
+}
+
+pub(crate) fn copy_request(request: &Request<()>) -> Result<Request<()>, http::Error>
+{
+    let mut builder = Request::builder();
+
+    builder.method(request.method().clone())
+        .uri(request.uri().clone());
+
+    for (name, value) in request.headers()
+    {
+        builder.header(name, value.clone());
+    }
+
+    builder.body(())
+}
+
+/// Counts a request and emits the status, latency and size of its response.
+pub(crate) fn observe(metrics: &Metrics, started: Instant, response: Option<&Response<Vec<u8>>>)
+{
+    let latency = started.elapsed();
+    let bytes = response.map(|response| response.body().len());
+
+    metrics.record_request(bytes, latency);
+    event!(
+        debug,
+        status = ?response.map(Response::status),
+        latency_ms = latency.as_millis() as u64,
+        bytes = ?bytes,
+        "received response"
+    );
+}
+
+/// Counts the warnings of a parsed response, or the api error it held.
+pub(crate) fn record_parsed(metrics: &Metrics, result: Result<responses::Query, Error>)
+    -> Result<responses::Query, Error>
+{
+    match &result
+    {
+        Ok(response) => metrics.record_warnings(response.warnings.as_ref().map_or(0, |warnings| warnings.messages().len())),
+        Err(_) => metrics.record_error(),
+    }
+
+    result
+}
+
+/// The wait asked for by the `Retry-After` header, in seconds.
+pub(crate) fn retry_after<B>(response: &Response<B>) -> Option<Duration>
+{
+    response.headers()
+        .get("Retry-After")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Returns the body of a successful response, or the error it holds.
+///
+/// See [`response_error`].
+///
+/// [`response_error`]: fn.response_error.html
+#[cfg(feature = "async")]
+pub(crate) fn successful_body(response: Response<Vec<u8>>) -> Result<Vec<u8>, Error>
+{
+    match response_error(&response)
+    {
+        Some(err) => Err(err),
+        None => Ok(response.into_body()),
+    }
+}
+
+/// The error a response holds, if it isn't successful.
+///
+/// Responses flagged with a `MediaWiki-API-Error` header hold their api error,
+/// so it can be retried, as do `maxlag` errors sent without the header.
+/// `maxlag` errors get the wait from `Retry-After`. Other api errors are left
+/// for the body to be parsed.
+pub(crate) fn response_error(response: &Response<Vec<u8>>) -> Option<Error>
+{
+    let status = response.status();
+    let flagged = response.headers().contains_key("MediaWiki-API-Error");
+    let body = response.body();
+
+    if status.is_success() && !flagged && !mentions_maxlag(body)
+    {
+        return None;
+    }
+
+    match serde_json::from_slice::<WikiError>(body).map(Error::from)
+    {
+        Ok(Error::MaxLag(mut lag)) => {
+            lag.retry_after = retry_after(response);
+            Some(Error::MaxLag(lag))
+        },
+        Ok(err) if flagged || !status.is_success() => Some(err),
+        _ if status.is_success() => None,
+        _ => Some(Error::Status(status)),
+    }
+}
+
+/// Whether the body may hold a `maxlag` error, so only those are parsed twice.
+fn mentions_maxlag(body: &[u8]) -> bool
+{
+    const CODE: &[u8] = b"\"maxlag\"";
+
+    body.windows(CODE.len()).any(|window| window == CODE)
+}
+
+/// An iterator over the responses of a paginated query.
+///
+/// The iterator ends after the first error. The failed request can be retried
+/// by taking back the paginator with [`Responses::into_paginator`] and
+/// resuming it.
+///
+/// [`Responses::into_paginator`]: struct.Responses.html#method.into_paginator
+pub struct Responses<'c, 'a, T>
+{
+    client: &'c Client<T>,
+    paginator: Paginator<'a>,
+    failed: bool,
+}
+
+impl<'c, 'a, T> Responses<'c, 'a, T>
+{
+    pub fn paginator(&self) -> &Paginator<'a>
+    {
+        &self.paginator
+    }
+
+    pub fn into_paginator(self) -> Paginator<'a>
+    {
+        self.paginator
+    }
+}
+
+impl<'c, 'a, T: Transport> Iterator for Responses<'c, 'a, T>
+{
+    type Item = Result<responses::Query, Error>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if self.failed
+        {
+            return None;
+        }
+
+        let result = self.paginator.next_request()?
+            .map_err(Error::from)
+            .and_then(|request| self.client.send(request))
+            .and_then(|body| record_parsed(&self.client.metrics, self.paginator.handle_response(&body)));
+
+        self.failed = result.is_err();
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod client_tests
+{
+    use super::*;
+    use http::StatusCode;
+    use std::cell::RefCell;
+
+    fn respond(status: u16, body: &str) -> Result<Response<Vec<u8>>, Error>
+    {
+        let mut builder = Response::builder();
+
+        builder.status(status);
+
+        Ok(builder.body(body.as_bytes().to_vec()).unwrap())
+    }
+
+    #[test]
+    fn paginates_through_transport()
+    {
+        let sent = RefCell::new(Vec::new());
+        let transport = |request: Request<()>| {
+            let query = request.uri().query().unwrap().to_string();
+            let body = if query.contains("accontinue=B")
+            {
+                "{\"batchcomplete\":true,\"query\":{\"allcategories\":[{\"category\":\"B\"}]}}"
+            }
+            else
+            {
+                "{\"batchcomplete\":true,\"continue\":{\"accontinue\":\"B\",\"continue\":\"-||\"},\"query\":{\"allcategories\":[{\"category\":\"A\"}]}}"
+            };
+
+            assert!(request.headers()["user-agent"].to_str().unwrap().starts_with("wikiquery/"));
+            sent.borrow_mut().push(query);
+            respond(200, body)
+        };
+
+        let client = Client::new(transport);
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_limit("1");
+
+        let categories: Vec<String> = client.paginate(query)
+            .map(|response| response.unwrap())
+            .flat_map(|response| response.query.all_categories.unwrap_or_default())
+            .map(|category| category.category)
+            .collect();
+
+        assert_eq!(categories, vec!["A", "B"]);
+        assert_eq!(sent.borrow().len(), 2);
+    }
+
+    #[test]
+    fn unsuccessful_status_is_an_error()
+    {
+        let mut client = Client::new(|_| respond(503, "upstream connect error"));
+        let mut query = Query::new();
+
+        client.retry(RetryPolicy::none());
+        query.all_categories();
+
+        match client.execute(&mut query)
+        {
+            Err(Error::Status(status)) => assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let client = Client::new(|_| respond(500, "{\"error\":{\"code\":\"internal_api_error\",\"info\":\"Oops.\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}"));
+
+        match client.execute(&mut query)
+        {
+            Err(Error::Api(err)) => assert_eq!(err.error.code, "internal_api_error"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stops_after_an_error()
+    {
+        let client = Client::new(|_| Err(Error::Transport("connection reset".into())));
+        let mut query = Query::new();
+
+        query.pages().titles("Death");
+
+        let mut responses = client.paginate(query);
+
+        assert!(responses.next().unwrap().is_err());
+        assert!(responses.next().is_none());
+        assert!(!responses.into_paginator().is_done());
+    }
+
+    thread_local! {
+        static SLEPT: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record_sleep(duration: Duration)
+    {
+        SLEPT.with(|slept| slept.borrow_mut().push(duration));
+    }
+
+    fn lag_error(retry_after: &str) -> Result<Response<Vec<u8>>, Error>
+    {
+        let mut builder = Response::builder();
+
+        builder.header("MediaWiki-API-Error", "maxlag")
+            .header("Retry-After", retry_after);
+
+        Ok(builder.body(b"{\"error\":{\"code\":\"maxlag\",\"info\":\"Waiting for 10.64.0.1: 7 seconds lagged.\",\"host\":\"10.64.0.1\",\"lag\":7,\"type\":\"db\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}".to_vec()).unwrap())
+    }
+
+    #[test]
+    fn waits_and_retries_when_lagged()
+    {
+        let sent = RefCell::new(Vec::new());
+        let transport = |request: Request<()>| {
+            let query = request.uri().query().unwrap().to_string();
+
+            sent.borrow_mut().push(query);
+
+            if sent.borrow().len() < 3
+            {
+                lag_error("2")
+            }
+            else
+            {
+                respond(200, "{\"batchcomplete\":true,\"query\":{}}")
+            }
+        };
+
+        let mut client = Client::new(transport);
+        let mut query = Query::new();
+
+        client.maxlag("5")
+            .sleep_with(record_sleep);
+        query.all_categories();
+
+        assert!(client.execute(&mut query).unwrap().batch_complete);
+        assert_eq!(sent.borrow().len(), 3);
+        assert!(sent.borrow().iter().all(|query| query.ends_with("&maxlag=5")));
+        SLEPT.with(|slept| assert_eq!(*slept.borrow(), vec![Duration::from_secs(2); 2]));
+    }
+
+    #[test]
+    fn retries_lag_errors_sent_without_the_header()
+    {
+        let sent = RefCell::new(0);
+        let transport = |_| {
+            *sent.borrow_mut() += 1;
+
+            match *sent.borrow()
+            {
+                1 => respond(200, "{\"error\":{\"code\":\"maxlag\",\"info\":\"Waiting for 10.64.0.1: 7 seconds lagged.\",\"host\":\"10.64.0.1\",\"lag\":7,\"type\":\"db\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}"),
+                _ => respond(200, "{\"batchcomplete\":true,\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Replication lag\",\"description\":\"maxlag\"}]}}"),
+            }
+        };
+
+        let mut client = Client::new(transport);
+        let mut query = Query::new();
+
+        client.sleep_with(record_sleep);
+        query.pages()
+            .titles("Replication_lag")
+            .description();
+
+        assert!(client.execute(&mut query).unwrap().batch_complete);
+        assert_eq!(*sent.borrow(), 2);
+        SLEPT.with(|slept| assert_eq!(*slept.borrow(), vec![crate::retry::DEFAULT_LAG_WAIT]));
+    }
+
+    #[test]
+    fn send_response_returns_the_last_response_unchanged()
+    {
+        const LAGGED: &str = "{\"error\":{\"code\":\"maxlag\",\"info\":\"Waiting for 10.64.0.1: 7 seconds lagged.\",\"lag\":7,\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}";
+
+        let sent = RefCell::new(0);
+        let transport = |_| {
+            let mut builder = Response::builder();
+
+            *sent.borrow_mut() += 1;
+            builder.status(200)
+                .header("MediaWiki-API-Error", "maxlag")
+                .header("Retry-After", "5")
+                .header("X-Served-By", "mw1");
+
+            Ok(builder.body(LAGGED.as_bytes().to_vec()).unwrap())
+        };
+
+        let mut client = Client::new(transport);
+        let mut policy = RetryPolicy::new();
+
+        policy.max_retries(1);
+        client.retry(policy)
+            .sleep_with(|_| ());
+
+        let request = Request::get("https://en.wikipedia.org/w/api.php?action=query&format=json").body(()).unwrap();
+        let response = client.send_response(request).unwrap();
+
+        assert_eq!(*sent.borrow(), 2);
+        assert_eq!(response.headers()["mediawiki-api-error"], "maxlag");
+        assert_eq!(response.headers()["retry-after"], "5");
+        assert_eq!(response.headers()["x-served-by"], "mw1");
+        assert_eq!(response.body(), LAGGED.as_bytes());
+    }
+
+    #[test]
+    fn retries_transient_failures_only()
+    {
+        let sent = RefCell::new(0);
+        let transport = |_| {
+            *sent.borrow_mut() += 1;
+
+            match *sent.borrow()
+            {
+                1 => {
+                    let mut builder = Response::builder();
+
+                    builder.status(429)
+                        .header("Retry-After", "3");
+
+                    Ok(builder.body(Vec::new()).unwrap())
+                },
+                2 => Err(Error::Transport(Box::new(std::io::Error::from(std::io::ErrorKind::ConnectionReset)))),
+                3 => {
+                    let mut builder = Response::builder();
+
+                    builder.header("MediaWiki-API-Error", "ratelimited");
+
+                    Ok(builder.body(b"{\"error\":{\"code\":\"ratelimited\",\"info\":\"Slow down.\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}".to_vec()).unwrap())
+                },
+                _ => respond(200, "{\"error\":{\"code\":\"badvalue\",\"info\":\"Unrecognized value.\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1\"}"),
+            }
+        };
+
+        let retries = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = retries.clone();
+        let mut policy = RetryPolicy::new();
+
+        policy.max_retries(5)
+            .on_retry(move |retry| reported.lock().unwrap().push(retry.attempt));
+
+        let mut client = Client::new(transport);
+        let mut query = Query::new();
+
+        client.retry(policy)
+            .sleep_with(record_sleep);
+        query.all_categories();
+
+        match client.execute(&mut query)
+        {
+            Err(Error::Api(err)) => assert_eq!(err.error.code, "badvalue"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(*sent.borrow(), 4);
+        assert_eq!(*retries.lock().unwrap(), vec![1, 2, 3]);
+
+        let metrics = client.metrics().snapshot();
+
+        assert_eq!((metrics.requests, metrics.retries, metrics.errors), (4, 3, 1));
+        SLEPT.with(|slept| assert_eq!(slept.borrow()[0], Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn gives_up_after_lag_retries()
+    {
+        let mut wiki = crate::fake::FakeWiki::new();
+
+        wiki.lag(7.0);
+
+        let mut client = Client::new(wiki);
+        let mut query = Query::new();
+
+        let mut policy = RetryPolicy::new();
+
+        policy.max_retries(1);
+        client.retry(policy)
+            .sleep_with(record_sleep);
+        query.all_categories()
+            .ac_limit("10");
+        query.maxlag("5");
+
+        match client.execute(&mut query)
+        {
+            Err(Error::MaxLag(lag)) => {
+                assert_eq!(lag.lag, 7.0);
+                assert_eq!(lag.host.as_deref(), Some("10.64.0.1"));
+                assert_eq!(lag.retry_after, Some(Duration::from_secs(5)));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        SLEPT.with(|slept| assert_eq!(*slept.borrow(), vec![Duration::from_secs(5)]));
+
+        query.maxlag("10");
+        assert!(client.execute(&mut query).is_ok());
+    }
+
+    #[test]
+    fn resolves_max_limits_with_rights_fetched_once()
+    {
+        let sent = RefCell::new(Vec::new());
+        let transport = |request: Request<()>| {
+            let query = request.uri().query().unwrap().to_string();
+            let body = if query.contains("meta=userinfo")
+            {
+                "{\"batchcomplete\":true,\"query\":{\"userinfo\":{\"id\":1,\"name\":\"Bot\",\"rights\":[\"read\",\"apihighlimits\"]}}}"
+            }
+            else
+            {
+                "{\"batchcomplete\":true,\"query\":{\"categorymembers\":[]}}"
+            };
+
+            sent.borrow_mut().push(query);
+            respond(200, body)
+        };
+        let client = Client::new(transport);
+
+        for _ in 0..2
+        {
+            let mut query = Query::new();
+
+            query.category_members()
+                .cm_title("Category:Death")
+                .cm_limit(Limit::Max);
+
+            client.execute(&mut query).unwrap();
+        }
+
+        let sent = sent.borrow();
+
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].contains("uiprop=rights"));
+        assert!(sent[1..].iter().all(|query| query.split('&').any(|param| param == "cmlimit=5000")));
+        assert!(client.rights().unwrap().high_limits());
+    }
+
+    #[test]
+    fn sends_max_limits_unchanged_when_not_resolving()
+    {
+        let sent = RefCell::new(Vec::new());
+        let transport = |request: Request<()>| {
+            sent.borrow_mut().push(request.uri().query().unwrap().to_string());
+            respond(200, "{\"batchcomplete\":true,\"query\":{\"categorymembers\":[]}}")
+        };
+        let mut client = Client::new(transport);
+        let mut query = Query::new();
+
+        client.resolve_limits(false);
+        query.category_members()
+            .cm_title("Category:Death")
+            .cm_limit(Limit::Max);
+
+        client.execute(&mut query).unwrap();
+
+        let sent = sent.borrow();
+
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].split('&').any(|param| param == "cmlimit=max"));
+    }
+}
@@ -0,0 +1,552 @@
+//! Runs queries with an [`AsyncTransport`] and streams their items.
+//!
+//! Streams follow continuation automatically, fetching the next response only
+//! once every item of the previous one was taken. Dropping a stream cancels
+//! the request in flight, if any, and no further requests are sent.
+//!
+//! # Examples
+//! ```
+//! use futures::{executor, future, StreamExt};
+//! use http::Response;
+//! use wikiquery::client::asynchronous::AsyncClient;
+//! use wikiquery::requests::Query;
+//!
+//! # let transport = |_request| {
+//! #     future::ready(Ok(Response::new(br#"{"batchcomplete":true,"query":{"pages":[{"ns":0,"title":"Death","pageid":8221}]}}"#.to_vec())))
+//! # };
+//! let client = AsyncClient::new(transport);
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles("Death")
+//!     .info();
+//!
+//! let pages: Vec<_> = executor::block_on(client.pages(query, None).collect());
+//!
+//! assert_eq!(pages[0].as_ref().unwrap().title, "Death");
+//! ```
+//!
+//! [`AsyncTransport`]: ../../transport/trait.AsyncTransport.html
+
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, Stream, StreamExt};
+use http::Request;
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::batch::{PageBatches, Pages};
+use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::pagination::Paginator;
+use crate::requests::{Limit, Query};
+use crate::responses::{self, category_members, pages, stream::Item};
+use crate::retry::RetryPolicy;
+use crate::rights::{self, Rights};
+use crate::transport::AsyncTransport;
+use crate::user_agent::UserAgent;
+use super::{copy_request, observe, record_parsed, retry_after, successful_body, with_maxlag};
+
+/// Waits for a duration on the runtime's timer.
+pub type Sleep = fn(Duration) -> BoxFuture<'static, ()>;
+
+/// Executes queries with an async transport.
+#[derive(Clone)]
+pub struct AsyncClient<T>
+{
+    transport: T,
+    maxlag: Option<String>,
+    retry: Option<(RetryPolicy, Sleep)>,
+    user_agent: UserAgent,
+    metrics: Arc<Metrics>,
+    rights: Arc<OnceLock<Rights>>,
+    resolve_limits: bool,
+}
+
+impl<T: Default + AsyncTransport> Default for AsyncClient<T>
+{
+    fn default() -> AsyncClient<T>
+    {
+        AsyncClient::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AsyncClient<T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("AsyncClient")
+            .field("transport", &self.transport)
+            .field("maxlag", &self.maxlag)
+            .field("retry", &self.retry.as_ref().map(|(policy, _)| policy))
+            .field("user_agent", &self.user_agent)
+            .field("metrics", &self.metrics)
+            .field("rights", &self.rights)
+            .field("resolve_limits", &self.resolve_limits)
+            .finish()
+    }
+}
+
+impl<T: AsyncTransport> AsyncClient<T>
+{
+    pub fn new(transport: T) -> AsyncClient<T>
+    {
+        AsyncClient {
+            transport,
+            maxlag: None,
+            retry: None,
+            user_agent: UserAgent::default(),
+            metrics: Arc::new(Metrics::new()),
+            rights: Arc::new(OnceLock::new()),
+            resolve_limits: true,
+        }
+    }
+
+    /// Sends `maxlag` with every request that doesn't set it already.
+    ///
+    /// Async retries are off by default: lagged requests fail with
+    /// [`Error::MaxLag`], which holds how long to wait, until
+    /// [`AsyncClient::retry`] is set.
+    ///
+    /// [`Error::MaxLag`]: ../../error/enum.Error.html#variant.MaxLag
+    /// [`AsyncClient::retry`]: struct.AsyncClient.html#method.retry
+    pub fn maxlag<S: Into<String>>(&mut self, seconds: S) -> &mut Self
+    {
+        self.maxlag = Some(seconds.into());
+        self
+    }
+
+    /// Retries transient failures following `policy`, waiting with `sleep`.
+    ///
+    /// Unlike [`Client`], nothing is retried by default, since waiting needs
+    /// a timer from the runtime. With tokio, `sleep` can be
+    /// `|duration| Box::pin(tokio::time::sleep(duration))`.
+    ///
+    /// [`Client`]: ../struct.Client.html
+    pub fn retry(&mut self, policy: RetryPolicy, sleep: Sleep) -> &mut Self
+    {
+        self.retry = Some((policy, sleep));
+        self
+    }
+
+    /// Identifies the application in every request, see [`UserAgent`].
+    ///
+    /// [`UserAgent`]: ../../user_agent/struct.UserAgent.html
+    pub fn user_agent(&mut self, agent: UserAgent) -> &mut Self
+    {
+        self.user_agent = agent;
+        self
+    }
+
+    /// Counts the requests, retries and warnings of this client.
+    pub fn metrics(&self) -> &Metrics
+    {
+        &self.metrics
+    }
+
+    /// Counts into the given metrics instead, see [`Client::share_metrics`].
+    ///
+    /// [`Client::share_metrics`]: ../struct.Client.html#method.share_metrics
+    pub fn share_metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self
+    {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The rights of the account requests are sent as, fetched once.
+    ///
+    /// See [`Client::rights`].
+    ///
+    /// [`Client::rights`]: ../struct.Client.html#method.rights
+    pub async fn rights(&self) -> Result<&Rights, Error>
+    {
+        self.rights_for(None).await
+    }
+
+    /// The rights, fetched from the wiki of `like` if it's given.
+    async fn rights_for(&self, like: Option<&Request<()>>) -> Result<&Rights, Error>
+    {
+        if let Some(rights) = self.rights.get()
+        {
+            return Ok(rights);
+        }
+
+        let request = match like
+        {
+            Some(like) => rights::request_like(like)?,
+            None => Rights::query().build()?,
+        };
+        // Sent without resolving limits, which would need the rights again.
+        let body = self.send_resolved(request).await?;
+        let response = record_parsed(&self.metrics, responses::parse(&body, false))?;
+
+        Ok(self.rights.get_or_init(|| Rights::from_response(&response)))
+    }
+
+    /// Uses the given rights rather than fetching them.
+    pub fn assume_rights(&mut self, rights: Rights) -> &mut Self
+    {
+        self.rights = Arc::new(OnceLock::from(rights));
+        self
+    }
+
+    /// Whether limits of `max` are resolved before they're sent, see
+    /// [`Client::resolve_limits`].
+    ///
+    /// [`Client::resolve_limits`]: ../struct.Client.html#method.resolve_limits
+    pub fn resolve_limits(&mut self, resolve: bool) -> &mut Self
+    {
+        self.resolve_limits = resolve;
+        self
+    }
+
+    pub fn transport(&self) -> &T
+    {
+        &self.transport
+    }
+
+    pub fn into_transport(self) -> T
+    {
+        self.transport
+    }
+
+    /// Sends a request and returns the body of a successful response.
+    ///
+    /// See [`Client::send`].
+    ///
+    /// [`Client::send`]: ../struct.Client.html#method.send
+    pub async fn send(&self, mut request: Request<()>) -> Result<Vec<u8>, Error>
+    {
+        if self.resolve_limits && rights::asks_for_max(&request)
+        {
+            self.rights_for(Some(&request)).await?.resolve(&mut request)?;
+        }
+
+        self.send_resolved(request).await
+    }
+
+    async fn send_resolved(&self, request: Request<()>) -> Result<Vec<u8>, Error>
+    {
+        let mut request = with_maxlag(request, self.maxlag.as_ref())?;
+
+        self.user_agent.apply(&mut request)?;
+
+        instrument!(
+            self.send_with_retries(request),
+            "send",
+            modules = %crate::trace::modules(&request),
+            params = crate::trace::param_count(&request)
+        ).await
+    }
+
+    async fn send_with_retries(&self, request: Request<()>) -> Result<Vec<u8>, Error>
+    {
+        let mut attempt = 0;
+        let mut waited = Duration::from_secs(0);
+
+        loop
+        {
+            let started = Instant::now();
+            let (result, retry_after) = match self.transport.send(copy_request(&request)?).await
+            {
+                Ok(response) => {
+                    let retry_after = retry_after(&response);
+
+                    observe(&self.metrics, started, Some(&response));
+                    (successful_body(response), retry_after)
+                },
+                Err(err) => {
+                    observe(&self.metrics, started, None);
+                    (Err(err), None)
+                },
+            };
+
+            let err = match result
+            {
+                Ok(body) => return Ok(body),
+                Err(err) => err,
+            };
+
+            match self.retry.as_ref().and_then(|(policy, sleep)| Some((policy.next_delay(&err, attempt, retry_after, waited)?, sleep)))
+            {
+                Some((delay, sleep)) => {
+                    self.metrics.record_retry();
+                    sleep(delay).await;
+                    attempt += 1;
+                    waited += delay;
+                },
+                None => {
+                    self.metrics.record_error();
+                    return Err(err);
+                },
+            }
+        }
+    }
+
+    /// Sends a single request for the query and parses the response.
+    ///
+    /// The query isn't continued.
+    pub async fn execute(&self, query: &mut Query<'_>) -> Result<responses::Query, Error>
+    {
+        let body = self.send(query.build()?).await?;
+
+        record_parsed(&self.metrics, responses::parse(&body, query.is_legacy_format()))
+    }
+
+    /// Runs every batch of a pages query through all of its continuations and
+    /// merges their pages.
+    ///
+    /// See [`Client::execute_pages`].
+    ///
+    /// [`Client::execute_pages`]: ../struct.Client.html#method.execute_pages
+    pub async fn execute_pages(&self, batches: &PageBatches) -> Result<Pages, Error>
+    {
+        let mut responses = Vec::new();
+        let mut batches = batches.clone();
+
+        if batches.limit() == Limit::Max
+        {
+            batches.rights(self.rights().await?);
+        }
+
+        for query in batches.queries()
+        {
+            let mut batch = Box::pin(self.paginate(query));
+
+            while let Some(response) = batch.next().await
+            {
+                responses.push(response?);
+            }
+        }
+
+        Ok(batches.merge(responses))
+    }
+
+    /// Streams the responses of the query through all of its continuations.
+    ///
+    /// The stream ends after the first error.
+    pub fn paginate<'s, 'a: 's>(&'s self, query: Query<'a>)
+        -> impl Stream<Item = Result<responses::Query, Error>> + 's
+    {
+        self.resume(Paginator::new(query))
+    }
+
+    /// Streams the responses of a paginator, for example one resumed from a
+    /// checkpoint.
+    pub fn resume<'s, 'a: 's>(&'s self, paginator: Paginator<'a>)
+        -> impl Stream<Item = Result<responses::Query, Error>> + 's
+    {
+        stream::unfold(Some(paginator), move |paginator| async move {
+            let mut paginator = paginator?;
+            let request = match paginator.next_request()?
+            {
+                Ok(request) => request,
+                Err(err) => return Some((Err(Error::from(err)), None)),
+            };
+
+            let result = match self.send(request).await
+            {
+                Ok(body) => record_parsed(&self.metrics, paginator.handle_response(&body)),
+                Err(err) => Err(err),
+            };
+
+            match result
+            {
+                Ok(response) => Some((Ok(response), Some(paginator))),
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Streams the pages, categories and category members of the query.
+    ///
+    /// At most `cap` items are returned, and no request is sent once the cap
+    /// is reached. The stream ends after the first error.
+    pub fn items<'s, 'a: 's>(&'s self, query: Query<'a>, cap: Option<usize>)
+        -> impl Stream<Item = Result<Item, Error>> + 's
+    {
+        self.paginate(query)
+            .flat_map(|response| {
+                let items: Vec<Result<Item, Error>> = match response
+                {
+                    Ok(response) => response.query.into_items().into_iter().map(Ok).collect(),
+                    Err(err) => vec![Err(err)],
+                };
+
+                stream::iter(items)
+            })
+            .take(cap.unwrap_or(usize::MAX))
+    }
+
+    /// Streams the category members of the query.
+    ///
+    /// See [`AsyncClient::items`].
+    ///
+    /// [`AsyncClient::items`]: struct.AsyncClient.html#method.items
+    pub fn category_members<'s, 'a: 's>(&'s self, query: Query<'a>, cap: Option<usize>)
+        -> impl Stream<Item = Result<category_members::Data, Error>> + 's
+    {
+        self.items(query, cap)
+            .filter_map(|item| future::ready(item.map(Item::into_category_member).transpose()))
+    }
+
+    /// Streams the pages of the query.
+    ///
+    /// See [`AsyncClient::items`].
+    ///
+    /// [`AsyncClient::items`]: struct.AsyncClient.html#method.items
+    pub fn pages<'s, 'a: 's>(&'s self, query: Query<'a>, cap: Option<usize>)
+        -> impl Stream<Item = Result<pages::Data, Error>> + 's
+    {
+        self.items(query, cap)
+            .filter_map(|item| future::ready(item.map(Item::into_page).transpose()))
+    }
+}
+
+#[cfg(test)]
+mod asynchronous_tests
+{
+    use super::*;
+    use futures::executor::block_on;
+    use http::Response;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn members_transport(sent: Arc<AtomicUsize>)
+        -> impl Fn(Request<()>) -> future::Ready<Result<Response<Vec<u8>>, Error>>
+    {
+        move |request| {
+            let page = sent.fetch_add(1, Ordering::SeqCst);
+            let query = request.uri().query().unwrap();
+
+            assert_eq!(query.contains("cmcontinue"), page > 0);
+
+            let body = format!("{{\"continue\":{{\"cmcontinue\":\"page|{0}\",\"continue\":\"-||\"}},\"query\":{{\"categorymembers\":[{{\"title\":\"A{0}\"}},{{\"title\":\"B{0}\"}}]}}}}", page);
+
+            future::ready(Ok(Response::new(body.into_bytes())))
+        }
+    }
+
+    /// Records the queries sent, answering `meta=userinfo` with high limits.
+    #[derive(Default)]
+    struct Recorder
+    {
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl AsyncTransport for Recorder
+    {
+        fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+        {
+            let query = request.uri().query().unwrap().to_string();
+            let body = if query.contains("meta=userinfo")
+            {
+                "{\"batchcomplete\":true,\"query\":{\"userinfo\":{\"id\":1,\"name\":\"Bot\",\"rights\":[\"read\",\"apihighlimits\"]}}}"
+            }
+            else
+            {
+                "{\"batchcomplete\":true,\"query\":{\"categorymembers\":[]}}"
+            };
+
+            self.sent.lock().unwrap().push(query);
+            Box::pin(future::ready(Ok(Response::new(body.as_bytes().to_vec()))))
+        }
+    }
+
+    fn members_query() -> Query<'static>
+    {
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Category:War")
+            .cm_limit("2");
+
+        query
+    }
+
+    #[test]
+    fn streams_items_up_to_the_cap()
+    {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let client = AsyncClient::new(members_transport(sent.clone()));
+
+        let titles: Vec<String> = block_on(client.category_members(members_query(), Some(5))
+            .map(|member| member.unwrap().title.unwrap())
+            .collect());
+
+        assert_eq!(titles, vec!["A0", "B0", "A1", "B1", "A2"]);
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn dropping_the_stream_stops_requests()
+    {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let client = AsyncClient::new(members_transport(sent.clone()));
+
+        {
+            let mut members = Box::pin(client.category_members(members_query(), None));
+
+            block_on(members.next()).unwrap().unwrap();
+            block_on(members.next()).unwrap().unwrap();
+            block_on(members.next()).unwrap().unwrap();
+        }
+
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn stream_ends_after_an_error()
+    {
+        let client = AsyncClient::new(|_| future::ready(Err(Error::Transport("connection reset".into()))));
+
+        let results: Vec<_> = block_on(client.paginate(members_query()).collect());
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+    }
+
+    #[test]
+    fn retries_with_the_given_sleep()
+    {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let counted = sent.clone();
+        let mut client = AsyncClient::new(move |_| {
+            let mut builder = Response::builder();
+
+            if counted.fetch_add(1, Ordering::SeqCst) == 0
+            {
+                builder.status(502);
+            }
+
+            future::ready(Ok(builder.body(b"{\"batchcomplete\":true,\"query\":{}}".to_vec()).unwrap()))
+        });
+        let mut query = Query::new();
+
+        client.retry(RetryPolicy::new(), |_| Box::pin(future::ready(())));
+        query.all_categories();
+
+        assert!(block_on(client.execute(&mut query)).unwrap().batch_complete);
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn default_clients_resolve_max_limits()
+    {
+        let client = AsyncClient::<Recorder>::default();
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Category:Death")
+            .cm_limit(Limit::Max);
+
+        block_on(client.execute(&mut query)).unwrap();
+
+        let sent = client.transport().sent.lock().unwrap();
+
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains("meta=userinfo"));
+        assert!(sent[1].split('&').any(|param| param == "cmlimit=5000"));
+    }
+}
@@ -0,0 +1,548 @@
+//! Runs many independent queries with bounded concurrency.
+//!
+//! An [`Executor`] runs each query of a collection through all of its
+//! continuations with an [`AsyncClient`], keeping at most a number of queries
+//! running overall and per host. Queries are only taken from the collection
+//! when a slot frees up and the results are consumed, so a slow consumer
+//! holds back new requests. Queries waiting for a busy host don't hold a slot,
+//! and a few of them are queued so queries for other hosts can run. Each
+//! result is paired with the key its query was given with, and results come
+//! back in that order unless unordered results are allowed.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use futures::{executor, StreamExt};
+//! use wikiquery::client::asynchronous::AsyncClient;
+//! use wikiquery::client::executor::Executor;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::Query;
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("Death").category("Category:Life"));
+//! wiki.add_page(FakePage::new("Birth").category("Category:Life"));
+//!
+//! let client = AsyncClient::new(wiki);
+//! let mut executor = Executor::new(&client);
+//!
+//! executor.concurrency(16)
+//!     .per_host(4);
+//!
+//! let queries = vec!["Category:Life", "Category:War"].into_iter().map(|category| {
+//!     let mut query = Query::new();
+//!
+//!     query.category_members()
+//!         .cm_title(category);
+//!
+//!     (category, query)
+//! });
+//!
+//! let results: Vec<_> = executor::block_on(executor.run(queries).collect());
+//!
+//! for (category, responses) in results
+//! {
+//!     let members: usize = responses.unwrap().iter().map(|response| response.query.item_count()).sum();
+//!
+//!     println!("{} has {} members", category, members);
+//! }
+//! # }
+//! ```
+//!
+//! [`Executor`]: struct.Executor.html
+//! [`AsyncClient`]: ../asynchronous/struct.AsyncClient.html
+
+use futures::future::{self, Future};
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::error::Error;
+use crate::requests::Query;
+use crate::responses;
+use crate::transport::AsyncTransport;
+use super::asynchronous::AsyncClient;
+
+/// How many queries run at once by default.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// How many queries run at once against the same host by default.
+pub const DEFAULT_PER_HOST: usize = 4;
+
+/// How many queries wait for a busy host by default.
+pub const DEFAULT_READ_AHEAD: usize = 64;
+
+/// Runs queries with an async client, a bounded number at a time.
+#[derive(Debug)]
+pub struct Executor<'c, T>
+{
+    client: &'c AsyncClient<T>,
+    concurrency: usize,
+    per_host: usize,
+    read_ahead: usize,
+    ordered: bool,
+}
+
+impl<'c, T: AsyncTransport> Executor<'c, T>
+{
+    pub fn new(client: &'c AsyncClient<T>) -> Executor<'c, T>
+    {
+        Executor {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            per_host: DEFAULT_PER_HOST,
+            read_ahead: DEFAULT_READ_AHEAD,
+            ordered: true,
+        }
+    }
+
+    /// The most queries running at once. Defaults to [`DEFAULT_CONCURRENCY`].
+    ///
+    /// [`DEFAULT_CONCURRENCY`]: constant.DEFAULT_CONCURRENCY.html
+    pub fn concurrency(&mut self, concurrency: usize) -> &mut Self
+    {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The most queries running at once against the same host. Defaults to
+    /// [`DEFAULT_PER_HOST`].
+    ///
+    /// [`DEFAULT_PER_HOST`]: constant.DEFAULT_PER_HOST.html
+    pub fn per_host(&mut self, per_host: usize) -> &mut Self
+    {
+        self.per_host = per_host.max(1);
+        self
+    }
+
+    /// The most queries waiting for a busy host, while the queries after them
+    /// run against other hosts. Defaults to [`DEFAULT_READ_AHEAD`].
+    ///
+    /// [`DEFAULT_READ_AHEAD`]: constant.DEFAULT_READ_AHEAD.html
+    pub fn read_ahead(&mut self, read_ahead: usize) -> &mut Self
+    {
+        self.read_ahead = read_ahead.max(1);
+        self
+    }
+
+    /// Whether results come back in the order of their queries, which is the
+    /// default.
+    ///
+    /// Unordered results come back as soon as they're done, so one slow query
+    /// doesn't hold back the others.
+    pub fn ordered(&mut self, ordered: bool) -> &mut Self
+    {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Streams the responses of every query, paired with its key.
+    ///
+    /// Each query is run through all of its continuations. A query that fails
+    /// gives its first error, and the others carry on.
+    pub fn run<'s, K, I>(&'s self, queries: I)
+        -> impl Stream<Item = (K, Result<Vec<responses::Query>, Error>)> + 's
+        where I: IntoIterator<Item = (K, Query<'static>)>,
+              I::IntoIter: 's,
+              K: 's
+    {
+        let client: &'s AsyncClient<T> = self.client;
+        let jobs = queries.into_iter().map(|(key, mut query)| {
+            // A query that can't be built fails when it's run.
+            let host = query.uri().ok()
+                .and_then(|uri| uri.host().map(str::to_string))
+                .unwrap_or_default();
+
+            (key, host, query)
+        });
+        let mut schedule = Schedule::new(jobs, move |query| run_query(client, query));
+
+        schedule.concurrency = self.concurrency;
+        schedule.per_host = self.per_host;
+        schedule.read_ahead = self.read_ahead;
+        schedule.ordered = self.ordered;
+
+        stream::poll_fn(move |cx| schedule.poll_next(cx))
+    }
+}
+
+/// Runs a query through all of its continuations.
+async fn run_query<T: AsyncTransport>(client: &AsyncClient<T>, query: Query<'static>)
+    -> Result<Vec<responses::Query>, Error>
+{
+    client.paginate(query)
+        .fold(Ok(Vec::new()), |responses, response| {
+            future::ready(responses.and_then(|mut responses: Vec<_>| {
+                responses.push(response?);
+                Ok(responses)
+            }))
+        })
+        .await
+}
+
+/// Starts jobs within the limits, queueing those whose host is busy.
+///
+/// Jobs are taken from `jobs` as `(key, host, job)` and started with `start`.
+/// Ordered results wait for the results before them, and no more jobs are
+/// taken than can be running, queued or waiting for `concurrency` slots plus
+/// `read_ahead`.
+struct Schedule<K, J, I, F, Fut: Future>
+{
+    jobs: I,
+    start: F,
+    concurrency: usize,
+    per_host: usize,
+    read_ahead: usize,
+    ordered: bool,
+    taken: usize,
+    exhausted: bool,
+    keys: HashMap<usize, (K, String)>,
+    queued: HashMap<String, VecDeque<(usize, J)>>,
+    queued_len: usize,
+    hosts: HashMap<String, usize>,
+    running: FuturesUnordered<Indexed<Fut>>,
+    done: BTreeMap<usize, (K, Fut::Output)>,
+    returned: usize,
+}
+
+impl<K, J, I, F, Fut> Schedule<K, J, I, F, Fut>
+    where I: Iterator<Item = (K, String, J)>,
+          F: FnMut(J) -> Fut,
+          Fut: Future
+{
+    fn new(jobs: I, start: F) -> Schedule<K, J, I, F, Fut>
+    {
+        Schedule {
+            jobs,
+            start,
+            concurrency: DEFAULT_CONCURRENCY,
+            per_host: DEFAULT_PER_HOST,
+            read_ahead: DEFAULT_READ_AHEAD,
+            ordered: true,
+            taken: 0,
+            exhausted: false,
+            keys: HashMap::new(),
+            queued: HashMap::new(),
+            queued_len: 0,
+            hosts: HashMap::new(),
+            running: FuturesUnordered::new(),
+            done: BTreeMap::new(),
+            returned: 0,
+        }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<(K, Fut::Output)>>
+    {
+        loop
+        {
+            if let Some(result) = self.done.remove(&self.returned)
+            {
+                self.returned += 1;
+                return Poll::Ready(Some(result));
+            }
+
+            self.fill();
+
+            let (index, output) = match self.running.poll_next_unpin(cx)
+            {
+                Poll::Ready(Some(finished)) => finished,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            let (key, host) = self.keys.remove(&index).unwrap();
+
+            if let Some(running) = self.hosts.get_mut(&host)
+            {
+                *running -= 1;
+            }
+
+            if !self.ordered
+            {
+                return Poll::Ready(Some((key, output)));
+            }
+
+            self.done.insert(index, (key, output));
+        }
+    }
+
+    /// Starts jobs until every slot is taken, or no more jobs may be taken.
+    fn fill(&mut self)
+    {
+        while self.running.len() < self.concurrency
+        {
+            if let Some((index, job)) = self.next_queued()
+            {
+                self.begin(index, job);
+                continue;
+            }
+
+            // Ordered results wait for the ones before them, and count too.
+            let waiting = self.ordered && self.taken - self.returned >= self.concurrency + self.read_ahead;
+
+            if self.exhausted || waiting || self.queued_len >= self.read_ahead
+            {
+                return;
+            }
+
+            let (key, host, job) = match self.jobs.next()
+            {
+                Some(job) => job,
+                None => {
+                    self.exhausted = true;
+                    return;
+                },
+            };
+            let index = self.taken;
+
+            self.taken += 1;
+            self.keys.insert(index, (key, host.clone()));
+
+            if self.has_room(&host)
+            {
+                self.begin(index, job);
+            }
+            else
+            {
+                self.queued.entry(host).or_default().push_back((index, job));
+                self.queued_len += 1;
+            }
+        }
+    }
+
+    /// The earliest queued job whose host has a free slot.
+    fn next_queued(&mut self) -> Option<(usize, J)>
+    {
+        let host = self.queued.iter()
+            .filter(|(host, _)| self.has_room(host))
+            .filter_map(|(host, queue)| Some((queue.front()?.0, host)))
+            .min()
+            .map(|(_, host)| host.clone())?;
+        let queue = self.queued.get_mut(&host)?;
+        let job = queue.pop_front();
+
+        if queue.is_empty()
+        {
+            self.queued.remove(&host);
+        }
+
+        self.queued_len -= 1;
+        job
+    }
+
+    fn has_room(&self, host: &str) -> bool
+    {
+        self.hosts.get(host).copied().unwrap_or(0) < self.per_host
+    }
+
+    fn begin(&mut self, index: usize, job: J)
+    {
+        let host = &self.keys[&index].1;
+
+        *self.hosts.entry(host.clone()).or_default() += 1;
+        self.running.push(Indexed {
+            index,
+            future: Box::pin((self.start)(job)),
+        });
+    }
+}
+
+/// A running job, resolving to its index and output.
+struct Indexed<Fut>
+{
+    index: usize,
+    future: Pin<Box<Fut>>,
+}
+
+impl<Fut: Future> Future for Indexed<Fut>
+{
+    type Output = (usize, Fut::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output>
+    {
+        let index = self.index;
+
+        self.future.as_mut().poll(cx).map(|output| (index, output))
+    }
+}
+
+#[cfg(test)]
+mod executor_tests
+{
+    use super::*;
+    use futures::channel::{mpsc, oneshot};
+    use futures::executor::block_on;
+    use futures::future::BoxFuture;
+    use http::{Request, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    type Arrivals = mpsc::UnboundedReceiver<(u64, oneshot::Sender<()>)>;
+    type Answer = BoxFuture<'static, Result<Response<Vec<u8>>, Error>>;
+
+    #[derive(Default)]
+    struct Load
+    {
+        running: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    /// Holds each request until the test releases it, so the test decides the
+    /// order they finish in.
+    fn held_transport(load: Arc<Load>)
+        -> (impl Fn(Request<()>) -> Answer, Arrivals)
+    {
+        let (arrived, arrivals) = mpsc::unbounded();
+        let transport = move |request: Request<()>| {
+            let load = load.clone();
+            let query = request.uri().query().unwrap().to_string();
+            let index: u64 = query.split("cmtitle=Category:")
+                .nth(1)
+                .and_then(|rest| rest.split('&').next())
+                .and_then(|index| index.parse().ok())
+                .unwrap();
+            let (release, released) = oneshot::channel();
+            let running = load.running.fetch_add(1, Ordering::SeqCst) + 1;
+
+            load.most.fetch_max(running, Ordering::SeqCst);
+            arrived.unbounded_send((index, release)).unwrap();
+
+            Box::pin(async move {
+                released.await.unwrap();
+                load.running.fetch_sub(1, Ordering::SeqCst);
+
+                let body = format!("{{\"batchcomplete\":true,\"query\":{{\"categorymembers\":[{{\"title\":\"Member {}\"}}]}}}}", index);
+
+                Ok(Response::new(body.into_bytes()))
+            }) as Answer
+        };
+
+        (transport, arrivals)
+    }
+
+    /// Waits until `held` requests arrived, or every request did, and
+    /// releases the latest first, so later queries finish first.
+    async fn release_latest_first<T>(arrivals: &mut mpsc::UnboundedReceiver<(T, oneshot::Sender<()>)>, held: usize, total: usize)
+    {
+        let mut holding = Vec::new();
+        let mut arrived = 0;
+
+        for _ in 0..total
+        {
+            while holding.len() < held && arrived < total
+            {
+                holding.push(arrivals.next().await.unwrap());
+                arrived += 1;
+            }
+
+            let (_, release) = holding.pop().unwrap();
+
+            release.send(()).unwrap();
+        }
+    }
+
+    fn queries() -> impl Iterator<Item = (u64, Query<'static>)>
+    {
+        (0..10).map(|index| {
+            let mut query = Query::new();
+
+            query.category_members()
+                .cm_title(format!("Category:{}", index));
+
+            (index, query)
+        })
+    }
+
+    #[test]
+    fn keeps_results_in_order_within_the_limits()
+    {
+        let load = Arc::new(Load::default());
+        let (transport, mut arrivals) = held_transport(load.clone());
+        let client = AsyncClient::new(transport);
+        let mut executor = Executor::new(&client);
+
+        executor.concurrency(5)
+            .per_host(3);
+
+        let (results, _) = block_on(future::join(
+            executor.run(queries()).collect::<Vec<_>>(),
+            release_latest_first(&mut arrivals, 3, 10),
+        ));
+        let keys: Vec<u64> = results.iter().map(|(key, _)| *key).collect();
+
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+        assert_eq!(load.most.load(Ordering::SeqCst), 3);
+
+        for (key, responses) in results
+        {
+            let members = responses.unwrap()[0].query.category_members.clone().unwrap();
+
+            assert_eq!(members[0].title.as_deref(), Some(format!("Member {}", key).as_str()));
+        }
+    }
+
+    #[test]
+    fn unordered_results_come_back_when_done()
+    {
+        let load = Arc::new(Load::default());
+        let (transport, mut arrivals) = held_transport(load.clone());
+        let client = AsyncClient::new(transport);
+        let mut executor = Executor::new(&client);
+
+        executor.concurrency(2)
+            .ordered(false);
+
+        let (keys, _) = block_on(future::join(
+            executor.run(queries()).map(|(key, _)| key).collect::<Vec<_>>(),
+            release_latest_first(&mut arrivals, 2, 10),
+        ));
+
+        assert_eq!(load.most.load(Ordering::SeqCst), 2);
+        assert_eq!(keys, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 0]);
+    }
+
+    #[test]
+    fn queries_waiting_on_a_busy_host_hold_no_slot()
+    {
+        let (arrived, mut arrivals) = mpsc::unbounded();
+        let jobs = vec![(0, "a"), (1, "a"), (2, "a"), (3, "b")]
+            .into_iter()
+            .map(|(key, host)| (key, host.to_string(), key));
+        let mut schedule = Schedule::new(jobs, |job| {
+            let (release, released) = oneshot::channel::<()>();
+
+            arrived.unbounded_send((job, release)).unwrap();
+            released
+        });
+
+        schedule.concurrency = 2;
+        schedule.per_host = 1;
+        schedule.ordered = false;
+
+        let results = stream::poll_fn(move |cx| schedule.poll_next(cx)).collect::<Vec<_>>();
+        let controller = async {
+            let mut started = Vec::new();
+
+            // The query for b starts while the others wait for a.
+            for wanted in &[2, 1, 1]
+            {
+                let arrived: Vec<_> = (&mut arrivals).take(*wanted).collect().await;
+
+                for (key, release) in arrived
+                {
+                    started.push(key);
+                    release.send(()).unwrap();
+                }
+            }
+
+            started
+        };
+
+        let (results, started) = block_on(future::join(results, controller));
+
+        assert_eq!(started, vec![0, 3, 1, 2]);
+        assert_eq!(results.len(), 4);
+    }
+}
@@ -1,18 +1,137 @@
 use serde;
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, PartialEq)]
+use std::fmt;
+use std::time::Duration;
+
+use crate::responses::Extra;
+
+/// The errors that can occur while running a query.
+#[derive(Debug)]
+pub enum Error
+{
+    /// The request couldn't be built.
+    Http(http::Error),
+    /// The response body couldn't be parsed.
+    Json(serde_json::Error),
+    /// The api returned an error.
+    Api(Box<WikiError>),
+    /// The server's databases lagged more than the query's `maxlag` allows.
+    MaxLag(Box<MaxLag>),
+    /// The request couldn't be sent or its response couldn't be read.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// The server answered with an unsuccessful status.
+    Status(http::StatusCode),
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Error::Http(err) => write!(f, "invalid request: {}", err),
+            Error::Json(err) => write!(f, "invalid response: {}", err),
+            Error::Api(err) => write!(f, "api error {}: {}", err.error.code, err.error.info),
+            Error::MaxLag(lag) => write!(f, "server lagged {} seconds behind: {}", lag.lag, lag.error.error.info),
+            Error::Transport(err) => write!(f, "transport error: {}", err),
+            Error::Status(status) => write!(f, "unsuccessful status: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for Error
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self
+        {
+            Error::Http(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Transport(err) => Some(err.as_ref()),
+            Error::Api(_) | Error::MaxLag(_) | Error::Status(_) => None,
+        }
+    }
+}
+
+impl From<http::Error> for Error
+{
+    fn from(err: http::Error) -> Error
+    {
+        Error::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for Error
+{
+    fn from(err: serde_json::Error) -> Error
+    {
+        Error::Json(err)
+    }
+}
+
+impl From<WikiError> for Error
+{
+    /// A `maxlag` error becomes [`Error::MaxLag`], any other [`Error::Api`].
+    ///
+    /// [`Error::MaxLag`]: enum.Error.html#variant.MaxLag
+    /// [`Error::Api`]: enum.Error.html#variant.Api
+    fn from(err: WikiError) -> Error
+    {
+        if err.error.code == "maxlag"
+        {
+            Error::MaxLag(Box::new(MaxLag::from(err)))
+        }
+        else
+        {
+            Error::Api(Box::new(err))
+        }
+    }
+}
+
+/// A query refused because the server lagged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxLag
+{
+    /// How many seconds the server lagged behind.
+    pub lag: f64,
+    /// The lagged database host.
+    pub host: Option<String>,
+    /// How long the server asked to wait before retrying, from the
+    /// `Retry-After` header.
+    pub retry_after: Option<Duration>,
+    pub error: WikiError,
+}
+
+impl From<WikiError> for MaxLag
+{
+    fn from(error: WikiError) -> MaxLag
+    {
+        MaxLag {
+            lag: error.error.extra.get("lag").and_then(|lag| lag.as_f64()).unwrap_or(0.0),
+            host: error.error.extra.get("host").and_then(|host| host.as_str()).map(String::from),
+            retry_after: None,
+            error,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WikiError
 {
     pub error: WikiErrorInner,
     #[serde(rename="servedby")]
-    pub served_by: String
+    pub served_by: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WikiErrorInner
 {
     pub code: String,
     pub info: String,
     pub docref: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
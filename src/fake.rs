@@ -0,0 +1,1151 @@
+//! An in-memory wiki that answers `api.php` queries, for testing.
+//!
+//! A [`FakeWiki`] holds a few pages and serves `list=allcategories`,
+//! `list=categorymembers`, `prop=info|description|extracts` and
+//! `meta=userinfo` in `formatversion=2`. It continues like the real api, warns
+//! about unknown values and clamped limits, and answers bad params with api
+//! errors. A lag can be set to test `maxlag` handling, and rights to test
+//! `apihighlimits`. It's a [`Transport`], so a
+//! [`Client`] can run queries against it without any network access. It's
+//! built with the `fake` feature, and the `fake-wiki` binary, built with
+//! `fake-server`, serves one over http.
+//!
+//! Wikis can be defined in a json fixture:
+//! ```json
+//! {
+//!     "pages": [
+//!         { "title": "Death", "description": "Permanent cessation of life", "categories": ["Death"] },
+//!         { "title": "Category:Death", "categories": ["Life"] }
+//!     ]
+//! }
+//! ```
+//!
+//! # Examples
+//! ```
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::Query;
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("War").category("Conflicts"))
+//!     .add_page(FakePage::new("Peace").category("Conflicts"));
+//!
+//! let client = Client::new(wiki);
+//! let mut query = Query::new();
+//!
+//! query.category_members()
+//!     .cm_title("Category:Conflicts")
+//!     .cm_limit("1");
+//!
+//! let titles: Vec<_> = client.paginate(query)
+//!     .flat_map(|response| response.unwrap().query.category_members.unwrap_or_default())
+//!     .map(|member| member.title.unwrap())
+//!     .collect();
+//!
+//! assert_eq!(titles, vec!["Peace", "War"]);
+//! ```
+//!
+//! [`FakeWiki`]: struct.FakeWiki.html
+//! [`Transport`]: ../transport/trait.Transport.html
+//! [`Client`]: ../client/struct.Client.html
+
+use http::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+use crate::batch::{MAX_EXTRACTS, MAX_TITLES_HIGH_LIMITS};
+use crate::error::Error;
+use crate::requests::decode_value;
+use crate::rights::{HIGH_LIMITS, MAX_LIMIT, MAX_LIMIT_HIGH};
+use crate::status::MAX_TITLES;
+use crate::transport::Transport;
+#[cfg(feature = "async")]
+use crate::transport::AsyncTransport;
+
+/// The `servedby` of error responses.
+pub const SERVED_BY: &str = "fake-wiki";
+
+const DEFAULT_LIMIT: usize = 10;
+const TOUCHED: &str = "2019-10-01T00:00:00Z";
+const LAGGED_HOST: &str = "10.64.0.1";
+const LAG_RETRY_AFTER: &str = "5";
+const ANON_NAME: &str = "127.0.0.1";
+const USER_NAME: &str = "FakeUser";
+const INVALID_CHARS: &[char] = &['#', '<', '>', '[', ']', '{', '}', '|'];
+
+/// A page of a [`FakeWiki`].
+///
+/// [`FakeWiki`]: struct.FakeWiki.html
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FakePage
+{
+    pub title: String,
+    /// Assigned when the page is added, if missing.
+    #[serde(rename="pageid", default, skip_serializing_if="Option::is_none")]
+    pub page_id: Option<u64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub extract: Option<String>,
+    /// The names of the categories the page is in, without the `Category:`
+    /// prefix.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// Whether the category is hidden, for category pages.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+impl FakePage
+{
+    pub fn new<S: Into<String>>(title: S) -> FakePage
+    {
+        FakePage {
+            title: normalize_title(&title.into()),
+            ..FakePage::default()
+        }
+    }
+
+    pub fn page_id(mut self, page_id: u64) -> FakePage
+    {
+        self.page_id = Some(page_id);
+        self
+    }
+
+    pub fn description<S: Into<String>>(mut self, description: S) -> FakePage
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn extract<S: Into<String>>(mut self, extract: S) -> FakePage
+    {
+        self.extract = Some(extract.into());
+        self
+    }
+
+    pub fn category<S: Into<String>>(mut self, category: S) -> FakePage
+    {
+        self.categories.push(normalize_name(&category.into()));
+        self
+    }
+
+    pub fn hidden(mut self) -> FakePage
+    {
+        self.hidden = true;
+        self
+    }
+
+    pub fn ns(&self) -> i32
+    {
+        namespace(&self.title)
+    }
+
+    fn page_type(&self) -> &'static str
+    {
+        match self.ns()
+        {
+            14 => "subcat",
+            6 => "file",
+            _ => "page",
+        }
+    }
+
+    fn sort_key(&self) -> String
+    {
+        let title = match self.title.find(':')
+        {
+            Some(i) if self.ns() != 0 => &self.title[i + 1..],
+            _ => &self.title,
+        };
+
+        title.to_uppercase()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// A wiki held in memory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FakeWiki
+{
+    pages: Vec<FakePage>,
+    /// How many seconds the databases lag, checked against `maxlag`.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    lag: Option<f64>,
+    /// The rights of the account queries are sent as.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    rights: Vec<String>,
+}
+
+impl FakeWiki
+{
+    pub fn new() -> FakeWiki
+    {
+        FakeWiki::default()
+    }
+
+    /// Parses a wiki from a json fixture.
+    pub fn from_json(json: &str) -> serde_json::Result<FakeWiki>
+    {
+        let fixture: FakeWiki = serde_json::from_str(json)?;
+        let mut wiki = FakeWiki::new();
+
+        wiki.lag = fixture.lag;
+        wiki.rights = fixture.rights;
+
+        for page in fixture.pages
+        {
+            wiki.add_page(page);
+        }
+
+        Ok(wiki)
+    }
+
+    /// Reads a wiki from a json fixture file.
+    pub fn from_fixture<P: AsRef<Path>>(path: P) -> io::Result<FakeWiki>
+    {
+        Ok(FakeWiki::from_json(&fs::read_to_string(path)?)?)
+    }
+
+    /// Adds a page, replacing any page with the same title.
+    pub fn add_page(&mut self, mut page: FakePage) -> &mut Self
+    {
+        page.title = normalize_title(&page.title);
+        page.categories = page.categories.iter().map(|c| normalize_name(c)).collect();
+
+        if page.page_id.is_none()
+        {
+            let max_id = self.pages.iter().filter_map(|p| p.page_id).max().unwrap_or(0);
+            page.page_id = Some(max_id + 1);
+        }
+
+        self.pages.retain(|p| p.title != page.title);
+        self.pages.push(page);
+        self
+    }
+
+    /// Makes the databases lag, so queries with a lower `maxlag` are refused.
+    pub fn lag(&mut self, seconds: f64) -> &mut Self
+    {
+        self.lag = Some(seconds);
+        self
+    }
+
+    /// Logs in as an account with the given rights. Queries are otherwise
+    /// sent anonymously, with just `read`.
+    ///
+    /// With `apihighlimits`, limits and titles are raised like for bots.
+    pub fn rights<I, S>(&mut self, rights: I) -> &mut Self
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        self.rights = rights.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn pages(&self) -> &[FakePage]
+    {
+        &self.pages
+    }
+
+    pub fn page(&self, title: &str) -> Option<&FakePage>
+    {
+        let title = normalize_title(title);
+
+        self.pages.iter().find(|p| p.title == title)
+    }
+
+    /// Answers a request to `/w/api.php`.
+    ///
+    /// Api errors are answered with a `200` status, like the real api. Other
+    /// paths are not found.
+    pub fn handle<B>(&self, request: &Request<B>) -> Response<Vec<u8>>
+    {
+        let mut builder = Response::builder();
+
+        if request.uri().path() != "/w/api.php"
+        {
+            return builder.status(StatusCode::NOT_FOUND)
+                .header("Content-Type", "text/plain")
+                .body(b"Not Found".to_vec())
+                .unwrap();
+        }
+
+        let params = parse_params(request.uri().query().unwrap_or(""));
+        let body = match self.answer(&params)
+        {
+            Ok(body) => body,
+            Err(err) => {
+                builder.header("MediaWiki-API-Error", err.code);
+
+                if err.code == "maxlag"
+                {
+                    builder.header("Retry-After", LAG_RETRY_AFTER)
+                        .header("X-Database-Lag", format!("{}", self.lag.unwrap_or(0.0).round()).as_str());
+                }
+
+                let mut error = err.extra;
+
+                error.insert("code".to_string(), json!(err.code));
+                error.insert("info".to_string(), json!(err.info));
+                error.insert("docref".to_string(), json!("See /w/api.php for API usage."));
+
+                json!({
+                    "error": error,
+                    "servedby": SERVED_BY,
+                })
+            },
+        };
+
+        builder.header("Content-Type", "application/json; charset=utf-8")
+            .body(body.to_string().into_bytes())
+            .unwrap()
+    }
+
+    fn answer(&self, params: &BTreeMap<String, String>) -> Result<Value, ApiError>
+    {
+        let action = params.get("action").map(String::as_str).unwrap_or("help");
+
+        if action != "query"
+        {
+            return Err(ApiError::bad_value("action", action));
+        }
+
+        if let (Some(lag), Some(maxlag)) = (self.lag, params.get("maxlag"))
+        {
+            let maxlag: f64 = maxlag.parse()
+                .map_err(|_| ApiError::new("badinteger", format!("Invalid value \"{}\" for integer parameter \"maxlag\".", maxlag)))?;
+
+            if lag > maxlag
+            {
+                let mut err = ApiError::new("maxlag", format!("Waiting for {}: {} seconds lagged.", LAGGED_HOST, lag));
+
+                err.extra.insert("host".to_string(), json!(LAGGED_HOST));
+                err.extra.insert("lag".to_string(), json!(lag));
+                err.extra.insert("type".to_string(), json!("db"));
+
+                return Err(err);
+            }
+        }
+
+        let mut out = Output::default();
+
+        for list in values(params, "list")
+        {
+            match list
+            {
+                "allcategories" => self.all_categories(params, &mut out)?,
+                "categorymembers" => self.category_members(params, &mut out)?,
+                other => out.warn("main", format!("Unrecognized value for parameter \"list\": {}.", other)),
+            }
+        }
+
+        if params.contains_key("titles")
+        {
+            self.titles(params, &mut out)?;
+        }
+
+        for meta in values(params, "meta")
+        {
+            match meta
+            {
+                "userinfo" => self.user_info(params, &mut out),
+                other => out.warn("main", format!("Unrecognized value for parameter \"meta\": {}.", other)),
+            }
+        }
+
+        Ok(out.into_json())
+    }
+
+    fn high_limits(&self) -> bool
+    {
+        self.rights.iter().any(|right| right == HIGH_LIMITS)
+    }
+
+    fn user_info(&self, params: &BTreeMap<String, String>, out: &mut Output)
+    {
+        let mut user_info = if self.rights.is_empty()
+        {
+            json!({ "id": 0, "name": ANON_NAME, "anon": true })
+        }
+        else
+        {
+            json!({ "id": 1, "name": USER_NAME })
+        };
+
+        if values(params, "uiprop").contains(&"rights")
+        {
+            let rights = if self.rights.is_empty() { vec!["read".to_string()] } else { self.rights.clone() };
+
+            user_info["rights"] = json!(rights);
+        }
+
+        out.query.insert("userinfo".to_string(), user_info);
+    }
+
+    fn categories(&self) -> BTreeMap<String, Vec<&FakePage>>
+    {
+        let mut categories: BTreeMap<String, Vec<&FakePage>> = BTreeMap::new();
+
+        for page in &self.pages
+        {
+            if page.ns() == 14
+            {
+                categories.entry(strip_namespace(&page.title).to_string()).or_default();
+            }
+
+            for category in &page.categories
+            {
+                categories.entry(category.clone()).or_default().push(page);
+            }
+        }
+
+        categories
+    }
+
+    fn all_categories(&self, params: &BTreeMap<String, String>, out: &mut Output) -> Result<(), ApiError>
+    {
+        let descending = direction(params, "acdir")?;
+        let limit = limit(params, "aclimit", out, "allcategories", self.high_limits())?;
+        let props = values(params, "acprop");
+        let bound = |key| params.get(key).map(|v| normalize_name(v));
+        let (from, to, prefix) = (bound("acfrom"), bound("acto"), bound("acprefix"));
+        let start = bound("accontinue").or(from);
+        let min = integer(params, "acmin")?;
+        let max = integer(params, "acmax")?;
+
+        let mut categories: Vec<_> = self.categories().into_iter()
+            .filter(|(name, _)| prefix.as_ref().is_none_or(|p| name.starts_with(p.as_str())))
+            .filter(|(_, members)| min.is_none_or(|min| members.len() >= min))
+            .filter(|(_, members)| max.is_none_or(|max| members.len() <= max))
+            .collect();
+
+        if descending
+        {
+            categories.reverse();
+        }
+
+        let in_range = |name: &String| {
+            let after_start = start.as_ref().is_none_or(|s| if descending { name <= s } else { name >= s });
+            let before_end = to.as_ref().is_none_or(|t| if descending { name >= t } else { name <= t });
+
+            after_start && before_end
+        };
+
+        let mut results = Vec::new();
+
+        for (name, members) in categories.into_iter().filter(|(name, _)| in_range(name))
+        {
+            if results.len() == limit
+            {
+                out.continue_with("accontinue", name.replace(' ', "_"));
+                break;
+            }
+
+            let mut category = json!({ "category": name });
+
+            if props.contains(&"size")
+            {
+                let count = |kind| members.iter().filter(|m| m.page_type() == kind).count();
+
+                category["size"] = json!(members.len());
+                category["pages"] = json!(count("page"));
+                category["files"] = json!(count("file"));
+                category["subcats"] = json!(count("subcat"));
+            }
+
+            if props.contains(&"hidden") && self.page(&format!("Category:{}", name)).is_some_and(|p| p.hidden)
+            {
+                category["hidden"] = json!(true);
+            }
+
+            results.push(category);
+        }
+
+        out.query.insert("allcategories".to_string(), Value::Array(results));
+
+        Ok(())
+    }
+
+    fn category_members(&self, params: &BTreeMap<String, String>, out: &mut Output) -> Result<(), ApiError>
+    {
+        let title = match (params.get("cmtitle"), params.get("cmpageid"))
+        {
+            (Some(title), None) => {
+                let title = normalize_title(title);
+
+                if namespace(&title) != 14
+                {
+                    return Err(ApiError::new("invalidcategory", "The category name you entered is not valid."));
+                }
+
+                title
+            },
+            (None, Some(id)) => {
+                self.pages.iter()
+                    .find(|p| p.page_id.map(|id| id.to_string()).as_ref() == Some(id) && p.ns() == 14)
+                    .map(|p| p.title.clone())
+                    .ok_or_else(|| ApiError::new("nosuchpageid", format!("There is no page with ID {}.", id)))?
+            },
+            (Some(_), Some(_)) => return Err(ApiError::new("invalidparammix", "The parameters \"cmtitle\" and \"cmpageid\" can not be used together.")),
+            (None, None) => return Err(ApiError::new("missingparam", "One of the parameters \"cmtitle\" and \"cmpageid\" is required.")),
+        };
+
+        let name = strip_namespace(&title).to_string();
+        let descending = direction(params, "cmdir")?;
+        let limit = limit(params, "cmlimit", out, "categorymembers", self.high_limits())?;
+        let types = values(params, "cmtype");
+        let props = match values(params, "cmprop")
+        {
+            props if props.is_empty() => vec!["ids", "title"],
+            props => props,
+        };
+
+        let mut members: Vec<&FakePage> = self.pages.iter()
+            .filter(|p| p.categories.contains(&name))
+            .filter(|p| types.is_empty() || types.contains(&p.page_type()))
+            .collect();
+
+        members.sort_by_key(|p| (p.sort_key(), p.page_id));
+
+        if descending
+        {
+            members.reverse();
+        }
+
+        if let Some(cont) = params.get("cmcontinue")
+        {
+            let bad_continue = || ApiError::new("badcontinue", "Invalid continue param. You should pass the original value returned by the previous query.");
+            let mut parts = cont.split('|');
+            let (_, key, id) = (parts.next(), parts.next().ok_or_else(bad_continue)?, parts.next().ok_or_else(bad_continue)?);
+            let position = (key.to_string(), Some(id.parse::<u64>().map_err(|_| bad_continue())?));
+
+            members.retain(|p| {
+                let member = (p.sort_key(), p.page_id);
+
+                if descending { member <= position } else { member >= position }
+            });
+        }
+
+        let mut results = Vec::new();
+
+        for page in members
+        {
+            if results.len() == limit
+            {
+                out.continue_with("cmcontinue", format!("{}|{}|{}", page.page_type(), page.sort_key(), page.page_id.unwrap()));
+                break;
+            }
+
+            let mut member = Map::new();
+
+            for prop in &props
+            {
+                match *prop
+                {
+                    "ids" => { member.insert("pageid".to_string(), json!(page.page_id)); },
+                    "title" => {
+                        member.insert("ns".to_string(), json!(page.ns()));
+                        member.insert("title".to_string(), json!(page.title));
+                    },
+                    "sortkey" => { member.insert("sortkey".to_string(), json!(page.sort_key())); },
+                    "sortkeyprefix" => { member.insert("sortkeyprefix".to_string(), json!("")); },
+                    "type" => { member.insert("type".to_string(), json!(page.page_type())); },
+                    "timestamp" => { member.insert("timestamp".to_string(), json!(TOUCHED)); },
+                    other => out.warn("categorymembers", format!("Unrecognized value for parameter \"cmprop\": {}.", other)),
+                }
+            }
+
+            results.push(Value::Object(member));
+        }
+
+        out.query.insert("categorymembers".to_string(), Value::Array(results));
+
+        Ok(())
+    }
+
+    fn titles(&self, params: &BTreeMap<String, String>, out: &mut Output) -> Result<(), ApiError>
+    {
+        let mut titles = values(params, "titles");
+        let max_titles = if self.high_limits() { MAX_TITLES_HIGH_LIMITS } else { MAX_TITLES };
+
+        if titles.len() > max_titles
+        {
+            out.warn("main", format!("Too many values supplied for parameter \"titles\". The limit is {}.", max_titles));
+            titles.truncate(max_titles);
+        }
+
+        let mut normalized = Vec::new();
+        let mut pages = Vec::new();
+        let mut found = Vec::new();
+
+        for raw in titles
+        {
+            if let Some(c) = raw.chars().find(|c| INVALID_CHARS.contains(c))
+            {
+                pages.push(json!({
+                    "title": raw,
+                    "invalidreason": format!("The requested page title contains invalid characters: \"{}\".", c),
+                    "invalid": true,
+                }));
+                continue;
+            }
+
+            let title = normalize_title(raw);
+
+            if title.is_empty()
+            {
+                pages.push(json!({ "title": raw, "invalidreason": "The requested page title is empty or contains only the name of a namespace.", "invalid": true }));
+                continue;
+            }
+
+            if title != raw
+            {
+                normalized.push(json!({ "fromencoded": false, "from": raw, "to": title }));
+            }
+
+            match self.page(&title)
+            {
+                Some(page) => {
+                    found.push(pages.len());
+                    pages.push(json!({ "pageid": page.page_id, "ns": page.ns(), "title": page.title }));
+                },
+                None if namespace(&title) == -1 => pages.push(json!({ "ns": -1, "title": title, "special": true })),
+                None => pages.push(json!({ "ns": namespace(&title), "title": title, "missing": true })),
+            }
+        }
+
+        for prop in values(params, "prop")
+        {
+            match prop
+            {
+                "info" => {
+                    for &i in &found
+                    {
+                        let page = self.page(pages[i]["title"].as_str().unwrap()).unwrap();
+                        let entry = &mut pages[i];
+
+                        entry["contentmodel"] = json!("wikitext");
+                        entry["pagelanguage"] = json!("en");
+                        entry["pagelanguagehtmlcode"] = json!("en");
+                        entry["pagelanguagedir"] = json!("ltr");
+                        entry["touched"] = json!(TOUCHED);
+                        entry["lastrevid"] = json!(page.page_id.unwrap() * 10);
+                        entry["length"] = json!(page.extract.as_ref().map_or(0, String::len));
+                    }
+                },
+                "description" => {
+                    for &i in &found
+                    {
+                        let page = self.page(pages[i]["title"].as_str().unwrap()).unwrap();
+
+                        if let Some(description) = &page.description
+                        {
+                            pages[i]["description"] = json!(description);
+                            pages[i]["descriptionsource"] = json!("local");
+                        }
+                    }
+                },
+                "extracts" => self.extracts(params, &mut pages, &found, out)?,
+                other => out.warn("main", format!("Unrecognized value for parameter \"prop\": {}.", other)),
+            }
+        }
+
+        if !normalized.is_empty()
+        {
+            out.query.insert("normalized".to_string(), Value::Array(normalized));
+        }
+
+        out.query.insert("pages".to_string(), Value::Array(pages));
+
+        Ok(())
+    }
+
+    fn extracts(&self, params: &BTreeMap<String, String>, pages: &mut [Value], found: &[usize], out: &mut Output) -> Result<(), ApiError>
+    {
+        let intro = params.contains_key("exintro");
+        let offset = integer(params, "excontinue")?.unwrap_or(0);
+        let chars = integer(params, "exchars")?;
+        let mut limit = match params.get("exlimit").map(String::as_str)
+        {
+            Some("max") => MAX_EXTRACTS,
+            _ => integer(params, "exlimit")?.unwrap_or(MAX_EXTRACTS),
+        };
+
+        if limit > MAX_EXTRACTS
+        {
+            out.warn("extracts", format!("exlimit may not be over {} (set to {}) for users.", MAX_EXTRACTS, limit));
+            limit = MAX_EXTRACTS;
+        }
+
+        if !intro && limit > 1 && found.len() > 1
+        {
+            out.warn("extracts", format!("exlimit was too large for a whole article extracts request, lowered to {}.", 1));
+            limit = 1;
+        }
+
+        for &i in found.iter().skip(offset).take(limit)
+        {
+            let page = self.page(pages[i]["title"].as_str().unwrap()).unwrap();
+            let mut extract = page.extract.clone().unwrap_or_default();
+
+            if intro
+            {
+                extract.truncate(extract.find("\n\n").unwrap_or(extract.len()));
+            }
+
+            if let Some(chars) = chars
+            {
+                if extract.chars().count() > chars
+                {
+                    extract = format!("{}...", extract.chars().take(chars).collect::<String>());
+                }
+            }
+
+            pages[i]["extract"] = json!(extract);
+        }
+
+        if offset + limit < found.len()
+        {
+            out.continue_with("excontinue", (offset + limit).to_string());
+            out.batch_complete = false;
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for FakeWiki
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        Ok(self.handle(&request))
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for FakeWiki
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(futures::future::ready(Ok(self.handle(&request))))
+    }
+}
+
+struct ApiError
+{
+    code: &'static str,
+    info: String,
+    extra: Map<String, Value>,
+}
+
+impl ApiError
+{
+    fn new<S: Into<String>>(code: &'static str, info: S) -> ApiError
+    {
+        ApiError {
+            code,
+            info: info.into(),
+            extra: Map::new(),
+        }
+    }
+
+    fn bad_value(param: &str, value: &str) -> ApiError
+    {
+        ApiError::new("badvalue", format!("Unrecognized value for parameter \"{}\": {}.", param, value))
+    }
+}
+
+struct Output
+{
+    query: Map<String, Value>,
+    continue_block: Map<String, Value>,
+    warnings: BTreeMap<&'static str, Vec<String>>,
+    batch_complete: bool,
+}
+
+impl Default for Output
+{
+    fn default() -> Output
+    {
+        Output {
+            query: Map::new(),
+            continue_block: Map::new(),
+            warnings: BTreeMap::new(),
+            batch_complete: true,
+        }
+    }
+}
+
+impl Output
+{
+    fn warn(&mut self, module: &'static str, warning: String)
+    {
+        self.warnings.entry(module).or_default().push(warning);
+    }
+
+    fn continue_with(&mut self, key: &str, value: String)
+    {
+        self.continue_block.insert(key.to_string(), json!(value));
+    }
+
+    fn into_json(self) -> Value
+    {
+        let mut body = Map::new();
+
+        if self.batch_complete
+        {
+            body.insert("batchcomplete".to_string(), json!(true));
+        }
+
+        if !self.continue_block.is_empty()
+        {
+            let mut continue_block = self.continue_block;
+            let marker = if self.batch_complete { "-||" } else { "||" };
+
+            continue_block.insert("continue".to_string(), json!(marker));
+            body.insert("continue".to_string(), Value::Object(continue_block));
+        }
+
+        if !self.warnings.is_empty()
+        {
+            let warnings = self.warnings.into_iter()
+                .map(|(module, warnings)| (module.to_string(), json!({ "warnings": warnings.join("\n") })))
+                .collect();
+
+            body.insert("warnings".to_string(), Value::Object(warnings));
+        }
+
+        if !self.query.is_empty()
+        {
+            body.insert("query".to_string(), Value::Object(self.query));
+        }
+
+        Value::Object(body)
+    }
+}
+
+fn parse_params(query: &str) -> BTreeMap<String, String>
+{
+    query.split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let mut parts = param.splitn(2, '=');
+            let key = decode_value(parts.next().unwrap());
+            let value = decode_value(parts.next().unwrap_or(""));
+
+            (key, value)
+        })
+        .collect()
+}
+
+fn values<'p>(params: &'p BTreeMap<String, String>, key: &str) -> Vec<&'p str>
+{
+    params.get(key)
+        .map(|value| value.split('|').filter(|v| !v.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+fn integer(params: &BTreeMap<String, String>, key: &str) -> Result<Option<usize>, ApiError>
+{
+    match params.get(key)
+    {
+        Some(value) => value.parse()
+            .map(Some)
+            .map_err(|_| ApiError::new("badinteger", format!("Invalid value \"{}\" for integer parameter \"{}\".", value, key))),
+        None => Ok(None),
+    }
+}
+
+fn limit(params: &BTreeMap<String, String>, key: &str, out: &mut Output, module: &'static str, high_limits: bool)
+    -> Result<usize, ApiError>
+{
+    let (max, who) = if high_limits { (MAX_LIMIT_HIGH, "bots") } else { (MAX_LIMIT, "users") };
+
+    if params.get(key).map(String::as_str) == Some("max")
+    {
+        return Ok(max);
+    }
+
+    let limit = integer(params, key)?.unwrap_or(DEFAULT_LIMIT);
+
+    if limit > max
+    {
+        out.warn(module, format!("{} may not be over {} (set to {}) for {}.", key, max, limit, who));
+        Ok(max)
+    }
+    else
+    {
+        Ok(limit.max(1))
+    }
+}
+
+fn direction(params: &BTreeMap<String, String>, key: &str) -> Result<bool, ApiError>
+{
+    match params.get(key).map(String::as_str)
+    {
+        None | Some("ascending") | Some("newer") => Ok(false),
+        Some("descending") | Some("older") => Ok(true),
+        Some(other) => Err(ApiError::bad_value(key, other)),
+    }
+}
+
+fn namespace(title: &str) -> i32
+{
+    match title.find(':').map(|i| &title[..i])
+    {
+        Some("Special") => -1,
+        Some("File") => 6,
+        Some("Template") => 10,
+        Some("Category") => 14,
+        _ => 0,
+    }
+}
+
+fn strip_namespace(title: &str) -> &str
+{
+    match title.find(':')
+    {
+        Some(i) if namespace(title) != 0 => &title[i + 1..],
+        _ => title,
+    }
+}
+
+fn capitalize(value: &str) -> String
+{
+    let mut chars = value.chars();
+
+    match chars.next()
+    {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Normalizes a category name the way the api does, without a namespace.
+fn normalize_name(name: &str) -> String
+{
+    capitalize(name.replace('_', " ").trim())
+}
+
+/// Normalizes a title the way the api does.
+fn normalize_title(title: &str) -> String
+{
+    let title = title.replace('_', " ");
+    let title = title.trim();
+
+    match title.find(':')
+    {
+        Some(i) => {
+            let prefixed = format!("{}:{}", capitalize(&title[..i].to_lowercase()), capitalize(title[i + 1..].trim()));
+
+            if namespace(&prefixed) != 0 { prefixed } else { capitalize(title) }
+        },
+        None => capitalize(title),
+    }
+}
+
+#[cfg(test)]
+mod fake_tests
+{
+    use super::*;
+    use crate::client::Client;
+    use crate::requests::{encode_value, Query};
+    use crate::responses::{merge::PageMerger, pages::PageStatus};
+
+    fn wiki() -> FakeWiki
+    {
+        FakeWiki::from_json(r#"{
+            "pages": [
+                { "title": "Death", "pageid": 8221, "description": "Permanent cessation of life", "extract": "Death is the end.\n\nMore on death.", "categories": ["Death"] },
+                { "title": "Afterlife", "extract": "An existence after death.", "categories": ["Death"] },
+                { "title": "Category:Death", "categories": ["Life"] },
+                { "title": "File:Skull.png", "categories": ["Death"] },
+                { "title": "Category:Hidden_maintenance", "hidden": true },
+                { "title": "Life", "categories": ["Life", "Hidden maintenance"] }
+            ]
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn lists_all_categories_with_continuation()
+    {
+        let client = Client::new(wiki());
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_prop("size|hidden")
+            .ac_limit("2");
+
+        let responses: Vec<_> = client.paginate(query).map(Result::unwrap).collect();
+        let categories: Vec<_> = responses.iter()
+            .flat_map(|r| r.query.all_categories.clone().unwrap())
+            .collect();
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].continue_block.as_ref().unwrap().ac_continue().unwrap(), "Life");
+        assert_eq!(categories.iter().map(|c| c.category.as_str()).collect::<Vec<_>>(), vec!["Death", "Hidden maintenance", "Life"]);
+        assert_eq!((categories[0].size, categories[0].pages, categories[0].files), (Some(3), Some(2), Some(1)));
+        assert!(categories[1].is_hidden());
+        assert_eq!(categories[2].subcats, Some(1));
+    }
+
+    #[test]
+    fn lists_category_members_by_type()
+    {
+        let client = Client::new(wiki());
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Category:Death")
+            .cm_prop("ids|title|type")
+            .cm_type("page|file")
+            .cm_limit("1");
+
+        let members: Vec<_> = client.paginate(query)
+            .flat_map(|r| r.unwrap().query.category_members.unwrap())
+            .map(|m| (m.title.unwrap(), m.page_type.unwrap()))
+            .collect();
+
+        use crate::responses::category_members::PageType;
+
+        assert_eq!(members, vec![
+            ("Afterlife".to_string(), PageType::Page),
+            ("Death".to_string(), PageType::Page),
+            ("File:Skull.png".to_string(), PageType::File),
+        ]);
+    }
+
+    #[test]
+    fn continues_extracts_across_a_batch()
+    {
+        let client = Client::new(wiki());
+        let mut query = Query::new();
+
+        query.pages()
+            .titles(encode_value("death|Afterlife|Nothing|Bad#title|Special:Random"))
+            .info()
+            .description()
+            .extracts();
+
+        let mut merger = PageMerger::new();
+        let mut pages = Vec::new();
+        let mut responses = client.paginate(query);
+
+        let mut first = responses.next().unwrap().unwrap();
+
+        assert!(!first.batch_complete);
+        assert!(first.warnings.as_ref().unwrap().extracts.is_some());
+        assert_eq!(first.query.normalized.as_ref().unwrap()[0].to, "Death");
+
+        pages.extend(merger.push(&mut first));
+
+        for response in responses
+        {
+            pages.extend(merger.push(&mut response.unwrap()));
+        }
+
+        let statuses: Vec<_> = pages.iter().map(|p| (p.title.as_str(), p.status())).collect();
+
+        assert_eq!(pages.len(), 5);
+        assert!(statuses.contains(&("Nothing", PageStatus::Missing)));
+        assert!(statuses.contains(&("Special:Random", PageStatus::Special)));
+
+        let death = pages.iter().find(|p| p.title == "Death").unwrap();
+        let afterlife = pages.iter().find(|p| p.title == "Afterlife").unwrap();
+
+        assert_eq!(death.page_id, Some(8221));
+        assert_eq!(death.description.as_deref(), Some("Permanent cessation of life"));
+        assert_eq!(death.extract.as_deref(), Some("Death is the end.\n\nMore on death."));
+        assert_eq!(afterlife.extract.as_deref(), Some("An existence after death."));
+        assert_eq!(afterlife.content_model.as_deref(), Some("wikitext"));
+    }
+
+    #[test]
+    fn answers_bad_params_with_api_errors()
+    {
+        let client = Client::new(wiki());
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Death");
+
+        match client.execute(&mut query)
+        {
+            Err(Error::Api(err)) => {
+                assert_eq!(err.error.code, "invalidcategory");
+                assert_eq!(err.served_by, SERVED_BY);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_limit("ten");
+
+        match client.execute(&mut query)
+        {
+            Err(Error::Api(err)) => assert_eq!(err.error.code, "badinteger"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn warns_about_clamped_limits_and_unknown_values()
+    {
+        let client = Client::new(wiki());
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_limit("5000");
+        query.pages()
+            .titles("Death")
+            .info()
+            .description();
+        query.params.insert("prop".into(), "info|revisions".to_string());
+
+        let response = client.execute(&mut query).unwrap();
+        let warnings = response.warnings.unwrap();
+
+        assert!(warnings.all_categories.unwrap().warnings.contains("aclimit may not be over 500"));
+        assert_eq!(warnings.extra["main"]["warnings"], "Unrecognized value for parameter \"prop\": revisions.");
+    }
+
+    #[test]
+    fn raises_limits_for_apihighlimits()
+    {
+        let mut wiki = wiki();
+        let mut query = Query::new();
+
+        query.user_info()
+            .ui_prop("rights");
+        query.all_categories()
+            .ac_limit("5000");
+
+        let response = Client::new(wiki.clone()).execute(&mut query).unwrap();
+        let user_info = response.query.user_info.unwrap();
+
+        assert_eq!(user_info.anon, Some(true));
+        assert_eq!(user_info.rights.unwrap(), vec!["read"]);
+        assert!(response.warnings.is_some());
+
+        wiki.rights(vec!["read", "apihighlimits"]);
+
+        let response = Client::new(wiki).execute(&mut query).unwrap();
+
+        assert_eq!(response.query.user_info.unwrap().rights.unwrap(), vec!["read", "apihighlimits"]);
+        assert!(response.warnings.is_none());
+    }
+}
@@ -1,6 +1,21 @@
+#[macro_use]
+mod trace;
+
 pub mod requests;
 pub mod responses;
+pub mod batch;
+pub mod checkpoint;
+pub mod client;
 pub mod error;
+#[cfg(any(test, feature = "fake"))]
+pub mod fake;
+pub mod metrics;
+pub mod pagination;
+pub mod retry;
+pub mod rights;
+pub mod status;
+pub mod transport;
+pub mod user_agent;
 
 #[cfg(test)]
 pub(crate) mod test;
\ No newline at end of file
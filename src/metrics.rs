@@ -0,0 +1,199 @@
+//! Counts the load a client puts on the api.
+//!
+//! Every [`Client`] and [`AsyncClient`] updates a [`Metrics`] as it sends
+//! requests and parses responses. Clients can share one to count a whole job,
+//! and a [`Snapshot`] of the counters can be serialized or written in the
+//! Prometheus text format for dashboards.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use std::sync::Arc;
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::metrics::Metrics;
+//! use wikiquery::requests::Query;
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("Death"));
+//!
+//! let metrics = Arc::new(Metrics::new());
+//! let mut client = Client::new(wiki);
+//! let mut query = Query::new();
+//!
+//! client.share_metrics(metrics.clone());
+//! query.pages()
+//!     .titles("Death")
+//!     .info();
+//!
+//! client.execute(&mut query).unwrap();
+//!
+//! let snapshot = metrics.snapshot();
+//!
+//! assert_eq!(snapshot.requests, 1);
+//! assert!(snapshot.to_prometheus("wikiquery").contains("wikiquery_requests_total 1"));
+//! # }
+//! ```
+//!
+//! [`Client`]: ../client/struct.Client.html
+//! [`AsyncClient`]: ../client/asynchronous/struct.AsyncClient.html
+//! [`Metrics`]: struct.Metrics.html
+//! [`Snapshot`]: struct.Snapshot.html
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counters updated by clients, safe to share between threads.
+#[derive(Debug, Default)]
+pub struct Metrics
+{
+    requests: AtomicU64,
+    bytes: AtomicU64,
+    retries: AtomicU64,
+    warnings: AtomicU64,
+    errors: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+/// The counters of a [`Metrics`] at one point in time.
+///
+/// [`Metrics`]: struct.Metrics.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot
+{
+    /// Requests sent, retries included.
+    pub requests: u64,
+    /// Bytes of response bodies received.
+    pub bytes: u64,
+    /// Requests sent again after a transient failure.
+    pub retries: u64,
+    /// Warning messages in parsed responses.
+    pub warnings: u64,
+    /// Requests that failed after any retries, or whose response held an api
+    /// error.
+    pub errors: u64,
+    /// Time spent waiting on responses.
+    pub latency: Duration,
+}
+
+impl Metrics
+{
+    pub fn new() -> Metrics
+    {
+        Metrics::default()
+    }
+
+    pub fn snapshot(&self) -> Snapshot
+    {
+        Snapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            warnings: self.warnings.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            latency: Duration::from_micros(self.latency_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Takes a snapshot and sets every counter back to zero.
+    pub fn reset(&self) -> Snapshot
+    {
+        Snapshot {
+            requests: self.requests.swap(0, Ordering::Relaxed),
+            bytes: self.bytes.swap(0, Ordering::Relaxed),
+            retries: self.retries.swap(0, Ordering::Relaxed),
+            warnings: self.warnings.swap(0, Ordering::Relaxed),
+            errors: self.errors.swap(0, Ordering::Relaxed),
+            latency: Duration::from_micros(self.latency_micros.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    /// Counts a request, and the body of its response if it got one.
+    pub(crate) fn record_request(&self, bytes: Option<usize>, latency: Duration)
+    {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes.unwrap_or(0) as u64, Ordering::Relaxed);
+        self.latency_micros.fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self)
+    {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_warnings(&self, warnings: usize)
+    {
+        self.warnings.fetch_add(warnings as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_error(&self)
+    {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Snapshot
+{
+    /// The counters in the Prometheus text format, named after `prefix`.
+    pub fn to_prometheus(&self, prefix: &str) -> String
+    {
+        let counters = [
+            ("requests_total", "Requests sent, retries included.", self.requests as f64),
+            ("response_bytes_total", "Bytes of response bodies received.", self.bytes as f64),
+            ("retries_total", "Requests sent again after a transient failure.", self.retries as f64),
+            ("warnings_total", "Warning messages in parsed responses.", self.warnings as f64),
+            ("errors_total", "Requests that failed after any retries, or whose response held an api error.", self.errors as f64),
+            ("latency_seconds_total", "Time spent waiting on responses.", self.latency.as_secs_f64()),
+        ];
+        let mut out = String::new();
+
+        for (name, help, value) in counters.iter()
+        {
+            let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} counter", prefix, name);
+            let _ = writeln!(out, "{}_{} {}", prefix, name, value);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests
+{
+    use super::*;
+
+    #[test]
+    fn counts_and_resets()
+    {
+        let metrics = Metrics::new();
+
+        metrics.record_request(Some(120), Duration::from_millis(30));
+        metrics.record_request(None, Duration::from_millis(10));
+        metrics.record_retry();
+        metrics.record_warnings(2);
+        metrics.record_error();
+
+        let expected = Snapshot {
+            requests: 2,
+            bytes: 120,
+            retries: 1,
+            warnings: 2,
+            errors: 1,
+            latency: Duration::from_millis(40),
+        };
+
+        assert_eq!(metrics.reset(), expected);
+        assert_eq!(metrics.snapshot(), Snapshot::default());
+
+        let text = expected.to_prometheus("job");
+
+        assert!(text.contains("# TYPE job_requests_total counter\njob_requests_total 2\n"));
+        assert!(text.contains("job_latency_seconds_total 0.04\n"));
+    }
+}
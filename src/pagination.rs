@@ -0,0 +1,250 @@
+//! Drives a query through all of its continuations without doing any IO.
+//!
+//! A [`Paginator`] hands out the next [`http`] [`Request`] to send and accepts
+//! the body of its response. It parses the body, continues the query and
+//! reports when there is nothing left to fetch. Sending the request is left to
+//! the caller, so any http stack, blocking or async, can be used.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::requests::Query;
+//! use wikiquery::pagination::Paginator;
+//!
+//! let mut query = Query::new();
+//!
+//! query.category_members()
+//!     .cm_title("Category:War")
+//!     .cm_limit("500");
+//!
+//! let mut paginator = Paginator::new(query);
+//!
+//! while let Some(request) = paginator.next_request()
+//! {
+//!     let request = request.unwrap();
+//!     /*
+//!         Send the request and read the body
+//!     */
+//!     # let body = br#"{"batchcomplete":true,"query":{"categorymembers":[]}}"#;
+//!
+//!     let response = paginator.handle_response(body).unwrap();
+//!
+//!     for member in response.query.category_members.unwrap_or_default()
+//!     {
+//!         println!("{:?}", member.title);
+//!     }
+//! }
+//! ```
+//!
+//! [`Paginator`]: struct.Paginator.html
+//! [`http`]: https://docs.rs/http
+//! [`Request`]: https://docs.rs/http/0.1/http/request/struct.Request.html
+
+use http::Request;
+
+use crate::checkpoint::Checkpoint;
+use crate::error::Error;
+use crate::requests::Query;
+use crate::responses;
+
+/// Tracks the continuation of a query across its responses.
+pub struct Paginator<'a>
+{
+    query: Query<'a>,
+    continue_block: Option<responses::ContinueBlock>,
+    responses: u64,
+    items: u64,
+    batch_complete: bool,
+}
+
+impl<'a> Paginator<'a>
+{
+    pub fn new(query: Query<'a>) -> Paginator<'a>
+    {
+        Paginator {
+            query,
+            continue_block: None,
+            responses: 0,
+            items: 0,
+            batch_complete: false,
+        }
+    }
+
+    /// Resumes a query from a [`Checkpoint`].
+    ///
+    /// The next request continues from the last response handled before the
+    /// checkpoint was taken.
+    ///
+    /// [`Checkpoint`]: ../checkpoint/struct.Checkpoint.html
+    pub fn resume(checkpoint: Checkpoint) -> Paginator<'static>
+    {
+        let mut query = Query::from_params(checkpoint.params);
+
+        if checkpoint.responses > 0
+        {
+            query.continue_query(&checkpoint.continue_block);
+        }
+
+        Paginator {
+            query,
+            continue_block: checkpoint.continue_block,
+            responses: checkpoint.responses,
+            items: checkpoint.items,
+            batch_complete: checkpoint.batch_complete,
+        }
+    }
+
+    /// Captures the state of the query so it can be resumed later.
+    pub fn checkpoint(&self) -> Checkpoint
+    {
+        Checkpoint {
+            params: self.query.spec(),
+            continue_block: self.continue_block.clone(),
+            responses: self.responses,
+            items: self.items,
+            batch_complete: self.batch_complete,
+        }
+    }
+
+    /// Builds the next request to send, or `None` once the query is done.
+    ///
+    /// Until [`Paginator::handle_response`] succeeds, the same request is
+    /// returned again, so a failed request can simply be retried.
+    ///
+    /// [`Paginator::handle_response`]: struct.Paginator.html#method.handle_response
+    pub fn next_request(&mut self) -> Option<Result<Request<()>, http::Error>>
+    {
+        if self.is_done()
+        {
+            None
+        }
+        else
+        {
+            Some(self.query.build())
+        }
+    }
+
+    /// Parses the body of the last request's response and continues the query.
+    ///
+    /// When the body holds an api error, [`Error::Api`] is returned and the
+    /// query isn't continued.
+    ///
+    /// [`Error::Api`]: ../error/enum.Error.html#variant.Api
+    pub fn handle_response(&mut self, body: &[u8]) -> Result<responses::Query, Error>
+    {
+        let response = responses::parse(body, self.query.is_legacy_format())?;
+
+        self.query.continue_query(&response.continue_block);
+        self.continue_block = response.continue_block.clone();
+        self.responses += 1;
+        self.items += response.query.item_count() as u64;
+        self.batch_complete = response.batch_complete;
+
+        event!(
+            debug,
+            responses = self.responses,
+            items = self.items,
+            done = self.is_done(),
+            "continuing query"
+        );
+
+        Ok(response)
+    }
+
+    /// Whether the last response had no continuation.
+    pub fn is_done(&self) -> bool
+    {
+        self.query.is_finished()
+    }
+
+    /// Whether the last response completed its batch of pages.
+    pub fn batch_complete(&self) -> bool
+    {
+        self.batch_complete
+    }
+
+    /// The number of responses handled so far.
+    pub fn responses(&self) -> u64
+    {
+        self.responses
+    }
+
+    /// The number of pages, categories and category members received so far.
+    pub fn items(&self) -> u64
+    {
+        self.items
+    }
+
+    pub fn query(&self) -> &Query<'a>
+    {
+        &self.query
+    }
+
+    pub fn into_query(self) -> Query<'a>
+    {
+        self.query
+    }
+}
+
+#[cfg(test)]
+mod pagination_tests
+{
+    use super::*;
+
+    fn request_query(paginator: &mut Paginator) -> Option<String>
+    {
+        paginator.next_request()
+            .map(|request| request.unwrap().uri().query().unwrap().to_string())
+    }
+
+    #[test]
+    fn follows_continuation_until_done()
+    {
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Category:War")
+            .cm_limit("2");
+
+        let mut paginator = Paginator::new(query);
+
+        let first = request_query(&mut paginator).unwrap();
+        assert!(!first.contains("cmcontinue"));
+
+        let response = paginator.handle_response(b"{\"continue\":{\"cmcontinue\":\"page|1|2\",\"continue\":\"-||\"},\"query\":{\"categorymembers\":[{\"title\":\"A\"},{\"title\":\"B\"}]}}").unwrap();
+        assert_eq!(response.query.category_members.unwrap().len(), 2);
+        assert!(!paginator.batch_complete());
+
+        let second = request_query(&mut paginator).unwrap();
+        assert!(second.contains("cmcontinue=page|1|2"));
+
+        paginator.handle_response(b"{\"batchcomplete\":true,\"query\":{\"categorymembers\":[{\"title\":\"C\"}]}}").unwrap();
+
+        assert!(paginator.is_done());
+        assert!(paginator.batch_complete());
+        assert_eq!(paginator.responses(), 2);
+        assert!(request_query(&mut paginator).is_none());
+    }
+
+    #[test]
+    fn api_errors_do_not_advance()
+    {
+        let mut query = Query::new();
+
+        query.all_categories();
+
+        let mut paginator = Paginator::new(query);
+        let first = request_query(&mut paginator);
+
+        let err = paginator.handle_response(b"{\"error\":{\"code\":\"ratelimited\",\"info\":\"Slow down.\",\"docref\":\"See api.php.\"},\"servedby\":\"mw1234\"}");
+
+        match err
+        {
+            Err(Error::Api(err)) => assert_eq!(err.error.code, "ratelimited"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(!paginator.is_done());
+        assert_eq!(paginator.responses(), 0);
+        assert_eq!(request_query(&mut paginator), first);
+    }
+}
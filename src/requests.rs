@@ -5,6 +5,7 @@
 //! - [`CategoryMembersQuery`]
 //! // PagesQuery is only partially implemented.
 //! - [`PagesQuery`]
+//! - [`UserInfoQuery`]
 //! 
 //! Find documentation for the different queries at [`mediawiki`].
 //! 
@@ -12,28 +13,164 @@
 //! [`PagesQuery`]: pages/struct.PagesQuery.html
 //! [`AllCategoriesQuery`]: struct.AllCategoriesQuery.html
 //! [`CategoryMembersQuery`]: struct.CategoryMembersQuery.html
+//! [`UserInfoQuery`]: user_info/struct.UserInfoQuery.html
 
 use http::{Request, Uri};
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::responses;
 
 pub mod all_categories;
 pub mod category_members;
 pub mod pages;
+pub mod user_info;
 
 use all_categories::AllCategoriesQuery;
 use category_members::CategoryMembersQuery;
 use pages::PagesQuery;
+use user_info::UserInfoQuery;
 
-pub type Params<'a> = HashMap<&'a str, String>;
+/// Query params keyed by name.
+/// 
+/// Keys are usually static, but continue params returned by the api are owned.
+pub type Params<'a> = HashMap<Cow<'a, str>, String>;
+
+/// Percent encodes a param value so it can be placed in a query string.
+/// 
+/// Everything but unreserved characters and the `|` value separator is encoded.
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::requests::encode_value;
+/// 
+/// assert_eq!(encode_value("Main page|Côte"), "Main%20page|C%C3%B4te");
+/// ```
+pub fn encode_value(value: &str) -> String
+{
+    value.bytes()
+        .fold(String::with_capacity(value.len()), |mut acc, byte| {
+            match byte
+            {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                | b'-' | b'.' | b'_' | b'~' | b'|' => acc.push(byte as char),
+                _ => acc.push_str(&format!("%{:02X}", byte)),
+            }
+
+            acc
+        })
+}
+
+/// Decodes a percent encoded param value, the reverse of [`encode_value`].
+/// 
+/// Invalid escapes are kept as they are.
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::requests::decode_value;
+/// 
+/// assert_eq!(decode_value("Main%20page|C%C3%B4te"), "Main page|Côte");
+/// ```
+/// 
+/// [`encode_value`]: fn.encode_value.html
+pub fn decode_value(value: &str) -> String
+{
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len()
+    {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex)
+        {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The value of a `limit` param.
+/// 
+/// Converts into the param value, so it can be passed to any limit setter.
+/// [`Limit::Max`] is sent as `max`, which a [`Client`] replaces with the
+/// most the account may ask for, see [`Rights`].
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::requests::{Limit, Query};
+/// 
+/// let mut query = Query::new();
+/// 
+/// query.category_members()
+///     .cm_title("Category:Lists_of_colors")
+///     .cm_limit(Limit::Max);
+/// ```
+/// 
+/// [`Limit::Max`]: enum.Limit.html#variant.Max
+/// [`Client`]: ../client/struct.Client.html
+/// [`Rights`]: ../rights/struct.Rights.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit
+{
+    /// The most the account may ask for.
+    Max,
+    Value(usize),
+}
+
+impl Limit
+{
+    /// The number this limit stands for, given the most the account may ask
+    /// for.
+    pub fn resolve(self, max: usize) -> usize
+    {
+        match self
+        {
+            Limit::Max => max,
+            Limit::Value(value) => value,
+        }
+    }
+}
+
+impl From<usize> for Limit
+{
+    fn from(value: usize) -> Limit
+    {
+        Limit::Value(value)
+    }
+}
+
+impl From<Limit> for String
+{
+    fn from(limit: Limit) -> String
+    {
+        match limit
+        {
+            Limit::Max => "max".to_string(),
+            Limit::Value(value) => value.to_string(),
+        }
+    }
+}
 
 /// A builder to generate mediawiki queries.
 /// 
 pub struct Query<'a>
 {
     pub params: Params<'a>,
+    continue_keys: HashSet<String>,
+    finished: bool,
 }
 
 impl<'a, 'b> Query<'a>
@@ -41,7 +178,9 @@ impl<'a, 'b> Query<'a>
     pub fn new() -> Query<'a>
     {
         Query {
-            params: HashMap::new()
+            params: HashMap::new(),
+            continue_keys: HashSet::new(),
+            finished: false,
         }
     }
     
@@ -83,16 +222,58 @@ impl<'a, 'b> Query<'a>
         PagesQuery::new(&mut self.params)
     }
 
+    /// Creates a new [`UserInfoQuery`]
+    /// 
+    /// Gets information on the account the query is sent as.
+    /// 
+    /// [`UserInfoQuery`]: user_info/struct.UserInfoQuery.html
+    pub fn user_info(&'b mut self) -> UserInfoQuery<'a, 'b>
+    {
+        UserInfoQuery::new(&mut self.params)
+    }
+
     /// Add the format param to the query
     /// 
     /// When [`Query::build`] is called, will assign `format=json` by default unless
     /// format was already set.
     pub fn format<S: Into<String>>(&mut self, format: S) -> &mut Self
     {
-        self.params.insert("format", format.into());
+        self.params.insert("format".into(), format.into());
+        self
+    }
+
+    /// Add the formatversion param to the query
+    /// 
+    /// When [`Query::build`] is called, will assign `formatversion=2` by default unless
+    /// the version was already set. Use `"1"` for mediawiki installs older than 1.25,
+    /// and parse their responses with [`responses::legacy`].
+    /// 
+    /// [`responses::legacy`]: ../responses/legacy/index.html
+    pub fn format_version<S: Into<String>>(&mut self, version: S) -> &mut Self
+    {
+        self.params.insert("formatversion".into(), version.into());
+        self
+    }
+
+    /// Add the maxlag param to the query
+    ///
+    /// The server refuses the query with a `maxlag` error while its databases
+    /// lag more than `seconds` behind, see [`Error::MaxLag`]. Wikimedia asks
+    /// bots to send `maxlag=5`.
+    ///
+    /// [`Error::MaxLag`]: ../error/enum.Error.html#variant.MaxLag
+    pub fn maxlag<S: Into<String>>(&mut self, seconds: S) -> &mut Self
+    {
+        self.params.insert("maxlag".into(), seconds.into());
         self
     }
 
+    /// Whether the query asks for `formatversion=1` responses.
+    pub fn is_legacy_format(&self) -> bool
+    {
+        self.params.get("formatversion").map(String::as_str) == Some("1")
+    }
+
     /// Generates an [`http`] [`Request`] from the query
     /// 
     /// # Examples
@@ -110,6 +291,8 @@ impl<'a, 'b> Query<'a>
     {
         let uri = self.uri()?;
 
+        event!(trace, params = self.params.len(), uri = %uri, "built query");
+
         Request::builder()
             .method("GET")
             .uri(uri)
@@ -131,9 +314,9 @@ impl<'a, 'b> Query<'a>
     /// ```
     pub fn uri(&mut self) -> Result<Uri, http::Error>
     {
-        self.params.entry("format").or_insert("json".to_string());
-        self.params.entry("formatversion").or_insert("2".to_string());
-        self.params.insert("action", "query".to_string());
+        self.params.entry("format".into()).or_insert("json".to_string());
+        self.params.entry("formatversion".into()).or_insert("2".to_string());
+        self.params.insert("action".into(), "query".to_string());
         
         let query_string = self.params.iter()
             .fold(
@@ -151,12 +334,17 @@ impl<'a, 'b> Query<'a>
     /// Continue a query for more data
     /// 
     /// When a query isn't able to return all the data, you can continue the
-    /// query from a [`Query::ContinueBlock`] to receive more.
+    /// query from a [`Query::ContinueBlock`] to receive more. Every continue
+    /// param in the block is copied into the query, and continue params from
+    /// earlier blocks that are no longer returned are removed, so modules
+    /// that have finished aren't continued again.
+    /// 
+    /// A `None` block means the query is finished, see [`Query::is_finished`].
     /// 
     /// # Examples
     /// ```
     /// use wikiquery::requests::Query;
-    /// # use wikiquery::responses::{Query as QueryResponse, QueryBlock, ContinueBlock};
+    /// # use wikiquery::responses::Query as QueryResponse;
     /// 
     /// let mut query = Query::new();
     /// 
@@ -169,59 +357,76 @@ impl<'a, 'b> Query<'a>
     ///     let resp = _;
     /// */
     /// 
-    /// # let resp = QueryResponse {
-    /// #     batch_complete: true,
-    /// #     query: QueryBlock {all_categories: None, category_members: None, pages: None},
-    /// #     warnings: None,
-    /// #     continue_block: Some(ContinueBlock {
-    /// #         r#continue: String::new(),
-    /// #         ac_continue: Some("Archives".to_string()),
-    /// #         cm_continue: None,
-    /// #         in_continue: None,
-    /// #         desc_continue: None,
-    /// #         ex_continue: None,
-    /// #     })
-    /// # };
+    /// # let resp: QueryResponse = serde_json::from_str(
+    /// #     r#"{"batchcomplete":true,"continue":{"accontinue":"Archives","continue":"-||"},"query":{}}"#
+    /// # ).unwrap();
     /// 
     /// query.continue_query(&resp.continue_block);
     /// 
     /// query.build().unwrap();
     /// ```
     /// [`Query::ContinueBlock`]: struct.ContinueBlock.html
+    /// [`Query::is_finished`]: struct.Query.html#method.is_finished
     pub fn continue_query(&mut self, continue_block: &Option<responses::ContinueBlock>) -> &Self
     {
-        if let Some(continue_block) = continue_block
-        {
-            self.params.insert("continue", continue_block.r#continue.to_string());
+        let continue_keys = &self.continue_keys;
 
-            if let Some(cont) = &continue_block.ac_continue
-            {
-                self.params.insert("accontinue", cont.to_string());
-            }
+        self.params.retain(|key, _| {
+            !key.ends_with("continue") && !continue_keys.contains(key.as_ref())
+        });
+        self.continue_keys.clear();
 
-            if let Some(cont) = &continue_block.cm_continue
-            {
-                self.params.insert("cmcontinue", cont.to_string());
-            }
+        match continue_block
+        {
+            Some(continue_block) => {
+                self.params.insert("continue".into(), continue_block.r#continue.to_string());
+
+                for (key, value) in continue_block.iter()
+                {
+                    self.continue_keys.insert(key.to_string());
+                    self.params.insert(Cow::Owned(key.to_string()), value);
+                }
+
+                self.finished = false;
+            },
+            None => self.finished = true,
+        }
 
-            if let Some(cont) = &continue_block.in_continue
-            {
-                self.params.insert("incontinue", cont.to_string());
-            }
+        self
+    }
 
-            if let Some(cont) = &continue_block.desc_continue
-            {
-                self.params.insert("desccontinue", cont.to_string());
-            }
+    /// Whether the last response continued from had no continue block.
+    pub fn is_finished(&self) -> bool
+    {
+        self.finished
+    }
 
-            if let Some(cont) = &continue_block.ex_continue
-            {
-                self.params.insert("excontinue", cont.to_string());
-            }
+    /// Creates a query from params, such as those of [`Query::spec`].
+    /// 
+    /// [`Query::spec`]: struct.Query.html#method.spec
+    pub fn from_params<I, K, V>(params: I) -> Query<'a>
+        where I: IntoIterator<Item = (K, V)>,
+              K: Into<Cow<'a, str>>,
+              V: Into<String>
+    {
+        let mut query = Query::new();
 
-        }
+        query.params = params.into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
 
-        self
+        query
+    }
+
+    /// The params of the query without those added by [`Query::continue_query`].
+    /// 
+    /// [`Query::continue_query`]: struct.Query.html#method.continue_query
+    pub fn spec(&self) -> BTreeMap<String, String>
+    {
+        self.params.iter()
+            .filter(|(key, _)| *key != "continue" && !self.continue_keys.contains(key.as_ref()))
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
     }
 }
 
@@ -238,7 +443,7 @@ trait SubQuery<'a, 'b> {
         }
         else
         {
-            params.insert(key, val);
+            params.insert(key.into(), val);
         }
 
         self
@@ -263,6 +468,7 @@ macro_rules! impl_sub_query
 impl_sub_query!(CategoryMembersQuery);
 impl_sub_query!(AllCategoriesQuery);
 impl_sub_query!(PagesQuery);
+impl_sub_query!(UserInfoQuery);
 
 #[cfg(test)]
 mod test
@@ -299,20 +505,31 @@ mod test
         assert_query_contains(&mut query, &contains);
     }
 
+    #[test]
+    fn test_format_version()
+    {
+        let mut query = Query::new();
+
+        query.all_categories();
+        assert!(!query.is_legacy_format());
+        assert_query_contains(&mut query, &["formatversion=2"]);
+
+        query.format_version("1");
+        assert!(query.is_legacy_format());
+        assert_query_contains(&mut query, &["formatversion=1"]);
+    }
+
     #[test]
     fn test_all_fields_continue_query()
     {
         let mut query = Query::new();
 
-        let continue_block = responses::ContinueBlock
-        {
-            r#continue: "-||".to_string(),
-            ac_continue: Some("a".to_string()),
-            cm_continue: Some("b".to_string()),
-            in_continue: Some("c".to_string()),
-            desc_continue: Some("d".to_string()),
-            ex_continue: Some("e".to_string()),
-        };
+        let continue_block: responses::ContinueBlock = serde_json::from_str(
+            "{\"continue\":\"-||\",\"accontinue\":\"a\",\"cmcontinue\":\"b\",\"incontinue\":\"c\",\"desccontinue\":\"d\",\"excontinue\":\"e\",\"gcmcontinue\":\"f\",\"rvcontinue\":\"g\",\"clcontinue\":\"h\",\"plcontinue\":\"i\",\"sroffset\":10}"
+        ).unwrap();
+
+        assert_eq!(continue_block.gcm_continue(), Some("f".to_string()));
+        assert_eq!(continue_block.sr_offset(), Some(10));
 
         query.continue_query(&Some(continue_block));
 
@@ -323,8 +540,83 @@ mod test
             "incontinue=c",
             "desccontinue=d",
             "excontinue=e",
+            "gcmcontinue=f",
+            "rvcontinue=g",
+            "clcontinue=h",
+            "plcontinue=i",
+            "sroffset=10",
         ];
 
         assert_query_contains(&mut query, &contains);
     }
-}
\ No newline at end of file
+
+    fn continue_block(body: &str) -> Option<responses::ContinueBlock>
+    {
+        Some(serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_continue_query_clears_finished_modules()
+    {
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_from("Lists_of_colors");
+
+        query.category_members()
+            .cm_title("Category:Lists_of_colors");
+
+        query.continue_query(&continue_block("{\"accontinue\":\"Lists_of_flags\",\"cmcontinue\":\"page|1|2\",\"continue\":\"-||\"}"));
+
+        assert!(!query.is_finished());
+        assert_query_contains(&mut query, &["accontinue=Lists_of_flags", "cmcontinue=page|1|2", "continue=-||"]);
+
+        query.continue_query(&continue_block("{\"cmcontinue\":\"page|3|4\",\"continue\":\"||allcategories\"}"));
+
+        assert_query_contains(&mut query, &["cmcontinue=page|3|4", "continue=||allcategories", "acfrom=Lists_of_colors"]);
+        assert_query_excludes(&mut query, &["accontinue="]);
+
+        query.continue_query(&None);
+
+        assert!(query.is_finished());
+        assert_query_excludes(&mut query, &["&continue=", "cmcontinue=", "accontinue="]);
+    }
+
+    #[test]
+    fn test_continue_query_clears_props_and_offsets()
+    {
+        let mut query = Query::new();
+
+        query.pages()
+            .titles("Death|Life")
+            .info()
+            .extracts()
+            .description()
+            .ex_continue("1");
+
+        query.continue_query(&continue_block("{\"excontinue\":\"2\",\"sroffset\":10,\"continue\":\"||info|description\"}"));
+
+        assert_query_contains(&mut query, &["excontinue=2", "sroffset=10"]);
+
+        query.continue_query(&continue_block("{\"desccontinue\":\"1\",\"continue\":\"||info|extracts\"}"));
+
+        assert_query_contains(&mut query, &["desccontinue=1", "continue=||info|extracts", "prop=info|extracts|description"]);
+        assert_query_excludes(&mut query, &["excontinue=", "sroffset="]);
+    }
+
+    #[test]
+    fn test_continue_query_clears_initial_continue()
+    {
+        let mut query = Query::new();
+
+        query.all_categories()
+            .ac_continue("Archives");
+
+        query.category_members()
+            .cm_title("Category:Lists_of_colors");
+
+        query.continue_query(&continue_block("{\"cmcontinue\":\"page|1|2\",\"continue\":\"||allcategories\"}"));
+
+        assert_query_excludes(&mut query, &["accontinue="]);
+    }
+}
@@ -65,6 +65,11 @@ impl<'a, 'b> AllCategoriesQuery<'a, 'b>
         self.add_param_value("acmax", value.into())
     }
 
+    /// Accepts a [`Limit`], where [`Limit::Max`] asks for the most the
+    /// account may.
+    /// 
+    /// [`Limit`]: ../enum.Limit.html
+    /// [`Limit::Max`]: ../enum.Limit.html#variant.Max
     pub fn ac_limit<S: Into<String>>(&mut self, value: S) -> &mut Self
     {
         self.add_param_value("aclimit", value.into())
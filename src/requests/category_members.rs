@@ -63,6 +63,11 @@ impl<'a, 'b> CategoryMembersQuery<'a, 'b>
         self.add_param_value("cmtype", value.into())
     }
 
+    /// Accepts a [`Limit`], where [`Limit::Max`] asks for the most the
+    /// account may.
+    /// 
+    /// [`Limit`]: ../enum.Limit.html
+    /// [`Limit::Max`]: ../enum.Limit.html#variant.Max
     pub fn cm_limit<S: Into<String>>(&mut self, value: S) -> &mut Self
     {
         self.add_param_value("cmlimit", value.into())
@@ -39,6 +39,19 @@ impl<'a, 'b> PagesQuery<'a, 'b>
         self.add_param_value("titles", value.into())
     }
 
+    pub fn page_ids<S: Into<String>>(&mut self, value: S) -> &mut Self
+    {
+        self.add_param_value("pageids", value.into())
+    }
+
+    /// Resolves redirects to their target pages.
+    /// 
+    /// The response lists each redirect followed in its `redirects` block.
+    pub fn redirects(&mut self) -> &mut Self
+    {
+        self.add_param_value("redirects", "true".to_string())
+    }
+
     /*
         -----
         Info Query methods
@@ -231,6 +244,18 @@ mod pages_tests
         assert_query_contains(&mut query, &contains);
     }
 
+    #[test]
+    fn page_ids_and_redirects() {
+        let mut query = Query::new();
+
+        query.pages()
+            .page_ids("1")
+            .page_ids("2")
+            .redirects();
+
+        assert_query_contains(&mut query, &["pageids=1|2", "redirects=true"]);
+    }
+
     #[test]
     fn description_all_fields() {
         let mut query = Query::new();
@@ -0,0 +1,68 @@
+use super::{Params, SubQuery};
+
+/// Generates a *userinfo* meta query.
+/// 
+/// Param documentation can be found at [`mediawiki:userinfo`]
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::requests::Query;
+/// 
+/// let mut query = Query::new();
+/// 
+/// query.user_info()
+///     .ui_prop("rights")
+///     .ui_prop("groups");
+/// 
+/// let request = query.build().unwrap();
+/// ```
+/// 
+/// [`mediawiki:userinfo`]: https://www.mediawiki.org/wiki/API:Userinfo
+pub struct UserInfoQuery<'a, 'b>
+{
+    pub(super) params: &'b mut Params<'a>
+}
+
+impl<'a, 'b> UserInfoQuery<'a, 'b>
+{
+    pub fn new(params: &'b mut Params<'a>) -> UserInfoQuery<'a, 'b>
+    {
+        let mut this = UserInfoQuery
+        {
+            params
+        };
+
+        this.add_param_value("meta", "userinfo".to_string());
+
+        this
+    }
+
+    pub fn ui_prop<S: Into<String>>(&mut self, value: S) -> &mut Self
+    {
+        self.add_param_value("uiprop", value.into())
+    }
+}
+
+#[cfg(test)]
+mod user_info_tests
+{
+    use crate::requests::Query;
+    use crate::test::helpers::*;
+
+    #[test]
+    fn test_all_fields_user_info()
+    {
+        let mut query = Query::new();
+
+        query.user_info()
+            .ui_prop("rights")
+            .ui_prop("groups");
+
+        let contains = [
+            "meta=userinfo",
+            "uiprop=rights|groups",
+        ];
+
+        assert_query_contains(&mut query, &contains);
+    }
+}
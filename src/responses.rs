@@ -1,98 +1,520 @@
+//! Types for deserializing and serializing mediawiki query responses.
+//! 
+//! Serializing writes the same field names the api uses, so responses can be
+//! stored and parsed again later.
+//! 
+//! Every struct keeps the fields it doesn't model in its `extra` map, so
+//! nothing sent by the api is lost. A [`DriftReport`] can collect those
+//! fields over a session to notice changes in the api.
+//! 
+//! [`DriftReport`]: drift/struct.DriftReport.html
+
 use serde;
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{Error, WikiError};
+
+pub mod borrowed;
+pub mod drift;
+pub mod legacy;
+pub mod merge;
+pub mod stream;
+
+/// Fields of a response that aren't modeled, keyed by their name.
+pub type Extra = HashMap<String, serde_json::Value>;
+
+/// Parses a response body, turning api errors into [`Error::Api`], or
+/// [`Error::MaxLag`] when the server lagged.
+/// 
+/// Set `legacy` for `formatversion=1` bodies.
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::responses;
+/// use wikiquery::error::Error;
+/// 
+/// let body = br#"{"error":{"code":"badvalue","info":"Unrecognized value.","docref":"See api.php."},"servedby":"mw1234"}"#;
+/// 
+/// match responses::parse(body, false)
+/// {
+///     Err(Error::Api(err)) => assert_eq!(err.error.code, "badvalue"),
+///     _ => unreachable!(),
+/// }
+/// ```
+/// 
+/// [`Error::Api`]: ../error/enum.Error.html#variant.Api
+/// [`Error::MaxLag`]: ../error/enum.Error.html#variant.MaxLag
+pub fn parse(body: &[u8], legacy: bool) -> Result<Query, Error>
+{
+    let value: serde_json::Value = serde_json::from_slice(body)?;
+    let value = if legacy { legacy::normalize(value) } else { value };
+
+    if value.get("error").is_some()
+    {
+        let err: WikiError = serde_json::from_value(value)?;
+
+        event!(debug, code = %err.error.code, servedby = %err.served_by, "api error");
+
+        return Err(err.into());
+    }
+
+    let query: Query = serde_json::from_value(value)?;
 
-use std::collections::HashMap;
+    event!(
+        debug,
+        bytes = body.len(),
+        items = query.query.item_count(),
+        batch_complete = query.batch_complete,
+        servedby = ?query.extra.get("servedby"),
+        "parsed response"
+    );
 
-#[derive(Debug, Deserialize)]
+    #[cfg(feature = "tracing")]
+    for (module, message) in query.warnings.iter().flat_map(WarningBlock::messages)
+    {
+        event!(warn, module, message, "api warning");
+    }
+
+    Ok(query)
+}
+
+/// The continuation of a query.
+/// 
+/// Every continue param returned by the api is kept, keyed by its name, so
+/// modules without an accessor can still be continued.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ContinueBlock
 {
     pub r#continue: String,
-    #[serde(rename="accontinue")]
-    pub ac_continue: Option<String>,
-    #[serde(rename="cmcontinue")]
-    pub cm_continue: Option<String>,
-    #[serde(rename="incontinue")]
-    pub in_continue: Option<String>,
-    #[serde(rename="desccontinue")]
-    pub desc_continue: Option<String>,
-    #[serde(rename="excontinue")]
-    pub ex_continue: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
+    #[serde(flatten)]
+    pub params: BTreeMap<String, serde_json::Value>,
+}
+
+impl ContinueBlock
+{
+    /// Returns the value of a continue param as it should be sent back.
+    pub fn get(&self, key: &str) -> Option<String>
+    {
+        self.params.get(key).map(|value| match value
+        {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    }
+
+    /// Iterates over the continue params, excluding `continue` itself.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, String)>
+    {
+        self.params.keys()
+            .filter_map(move |key| Some((key.as_str(), self.get(key)?)))
+    }
+
+    pub fn ac_continue(&self) -> Option<String>
+    {
+        self.get("accontinue")
+    }
+
+    pub fn cm_continue(&self) -> Option<String>
+    {
+        self.get("cmcontinue")
+    }
+
+    pub fn gcm_continue(&self) -> Option<String>
+    {
+        self.get("gcmcontinue")
+    }
+
+    pub fn in_continue(&self) -> Option<String>
+    {
+        self.get("incontinue")
+    }
+
+    pub fn desc_continue(&self) -> Option<String>
+    {
+        self.get("desccontinue")
+    }
+
+    pub fn ex_continue(&self) -> Option<String>
+    {
+        self.get("excontinue")
+    }
+
+    pub fn rv_continue(&self) -> Option<String>
+    {
+        self.get("rvcontinue")
+    }
+
+    pub fn cl_continue(&self) -> Option<String>
+    {
+        self.get("clcontinue")
+    }
+
+    pub fn pl_continue(&self) -> Option<String>
+    {
+        self.get("plcontinue")
+    }
+
+    pub fn sr_offset(&self) -> Option<u64>
+    {
+        self.params.get("sroffset")?.as_u64()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Normalized
+{
+    pub from: String,
+    pub to: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// A title that redirects to another page, returned when the query asks to
+/// resolve redirects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redirect
+{
+    pub from: String,
+    pub to: String,
+    #[serde(rename="tofragment", skip_serializing_if="Option::is_none")]
+    pub to_fragment: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// The account the query was sent as, returned for `meta=userinfo`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfo
+{
+    pub id: u64,
+    pub name: String,
+    /// Whether the query was sent without logging in.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub anon: Option<bool>,
+    /// Returned for `uiprop=rights`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub rights: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct QueryBlock
 {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub normalized: Option<Vec<Normalized>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub redirects: Option<Vec<Redirect>>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub pages: Option<Vec<pages::Data>>,
-    #[serde(rename="allcategories")]
+    #[serde(rename="allcategories", skip_serializing_if="Option::is_none")]
     pub all_categories: Option<Vec<all_categories::Data>>,
-    #[serde(rename="categorymembers")]
-    pub category_members: Option<Vec<category_members::Data>>
+    #[serde(rename="categorymembers", skip_serializing_if="Option::is_none")]
+    pub category_members: Option<Vec<category_members::Data>>,
+    #[serde(rename="userinfo", skip_serializing_if="Option::is_none")]
+    pub user_info: Option<UserInfo>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl QueryBlock
+{
+    /// The number of pages, categories and category members in the block.
+    pub fn item_count(&self) -> usize
+    {
+        self.pages.as_ref().map_or(0, Vec::len)
+            + self.all_categories.as_ref().map_or(0, Vec::len)
+            + self.category_members.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Takes the pages, categories and category members out of the block, in
+    /// that order.
+    pub fn into_items(self) -> Vec<stream::Item>
+    {
+        let pages = self.pages.unwrap_or_default().into_iter()
+            .map(|page| stream::Item::Page(Box::new(page)));
+        let categories = self.all_categories.unwrap_or_default().into_iter()
+            .map(stream::Item::Category);
+        let members = self.category_members.unwrap_or_default().into_iter()
+            .map(stream::Item::CategoryMember);
+
+        pages.chain(categories).chain(members).collect()
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WarningBlock
 {
-    #[serde(rename="allcategories")]
+    #[serde(rename="allcategories", skip_serializing_if="Option::is_none")]
     pub all_categories: Option<Warnings>,
-    #[serde(rename="categorymembers")]
+    #[serde(rename="categorymembers", skip_serializing_if="Option::is_none")]
     pub category_members: Option<Warnings>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub info: Option<Warnings>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub pages: Option<Warnings>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub description: Option<Warnings>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub extracts: Option<Warnings>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize)]
+impl WarningBlock
+{
+    /// Every warning message with the module that raised it.
+    ///
+    /// Modules may join several messages with newlines, they're split apart.
+    pub fn messages(&self) -> Vec<(&str, &str)>
+    {
+        let modules = [
+            ("allcategories", &self.all_categories),
+            ("categorymembers", &self.category_members),
+            ("info", &self.info),
+            ("pages", &self.pages),
+            ("description", &self.description),
+            ("extracts", &self.extracts),
+        ];
+        let known = modules.iter()
+            .filter_map(|(module, warnings)| Some((*module, warnings.as_ref()?.warnings.as_str())));
+        let extra = self.extra.iter()
+            .filter_map(|(module, warnings)| Some((module.as_str(), warnings.get("warnings")?.as_str()?)));
+
+        known.chain(extra)
+            .flat_map(|(module, warnings)| warnings.lines().map(move |message| (module, message)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Query
 {
-    #[serde(rename = "batchcomplete")]
+    #[serde(rename = "batchcomplete", default)]
     pub batch_complete: bool,
+    #[serde(default)]
     pub query: QueryBlock,
-    #[serde(rename = "continue")]
+    #[serde(rename = "continue", skip_serializing_if="Option::is_none")]
     pub continue_block: Option<ContinueBlock>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub warnings: Option<WarningBlock>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Warnings
 {
     pub warnings: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 pub mod all_categories
 {
     use super::*;
     
-    #[derive(Debug, Deserialize)]
+    use std::iter::Sum;
+    use std::ops::Add;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct Data
     {
         pub category: String,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub size: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub pages: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub files: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub subcats: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub hidden: Option<bool>,
+        #[serde(flatten)]
+        pub extra: Extra,
+    }
+
+    impl Data
+    {
+        /// Returns the counts of the category, if `acprop=size` was requested.
+        pub fn size_summary(&self) -> Option<CategorySize>
+        {
+            Some(CategorySize {
+                size: self.size?,
+                pages: self.pages?,
+                files: self.files?,
+                subcats: self.subcats?,
+            })
+        }
+
+        /// Whether the category is hidden. Requires `acprop=hidden`.
+        pub fn is_hidden(&self) -> bool
+        {
+            self.hidden.unwrap_or(false)
+        }
+    }
+
+    /// The member counts of a category.
+    /// 
+    /// Summaries can be added together to total several categories. Totals
+    /// stop at `u32::MAX` rather than overflowing.
+    /// 
+    /// # Examples
+    /// ```
+    /// use wikiquery::responses::all_categories::{Data, CategorySize};
+    /// 
+    /// # let categories: Vec<Data> = vec![];
+    /// let total: CategorySize = categories.iter()
+    ///     .filter_map(Data::size_summary)
+    ///     .sum();
+    /// ```
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct CategorySize
+    {
+        pub size: u32,
+        pub pages: u32,
+        pub files: u32,
+        pub subcats: u32,
+    }
+
+    impl CategorySize
+    {
+        pub fn is_empty(&self) -> bool
+        {
+            self.size == 0
+        }
+    }
+
+    impl Add for CategorySize
+    {
+        type Output = CategorySize;
+
+        fn add(self, other: CategorySize) -> CategorySize
+        {
+            CategorySize {
+                size: self.size.saturating_add(other.size),
+                pages: self.pages.saturating_add(other.pages),
+                files: self.files.saturating_add(other.files),
+                subcats: self.subcats.saturating_add(other.subcats),
+            }
+        }
+    }
+
+    impl Sum for CategorySize
+    {
+        fn sum<I: Iterator<Item = CategorySize>>(iter: I) -> CategorySize
+        {
+            iter.fold(CategorySize::default(), Add::add)
+        }
     }
 }
 
 pub mod category_members
 {
     use super::*;
+
+    use std::fmt;
     
-    #[derive(Debug, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct Data
     {
-        #[serde(rename="pageid")]
+        #[serde(rename="pageid", skip_serializing_if="Option::is_none")]
         pub page_id: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub ns: Option<u32>,
-        #[serde(rename="sortkey")]
-        pub sort_key: Option<String>,
-        #[serde(rename="sortkeyprefix")]
+        #[serde(rename="sortkey", skip_serializing_if="Option::is_none")]
+        pub sort_key: Option<SortKey>,
+        #[serde(rename="sortkeyprefix", skip_serializing_if="Option::is_none")]
         pub sort_key_prefix: Option<String>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub title: Option<String>,
-        #[serde(rename="type")]
-        pub page_type: Option<String>,
+        #[serde(rename="type", skip_serializing_if="Option::is_none")]
+        pub page_type: Option<PageType>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub timestamp: Option<String>,
+        #[serde(flatten)]
+        pub extra: Extra,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(rename_all="lowercase")]
+    pub enum PageType
+    {
+        Page,
+        Subcat,
+        File,
+    }
+
+    /// The hexadecimal sort key of a category member.
+    /// 
+    /// Can be passed back into a query to start or end a listing at this member.
+    /// 
+    /// # Examples
+    /// ```
+    /// use wikiquery::requests::Query;
+    /// # use wikiquery::responses::category_members::SortKey;
+    /// 
+    /// # let sort_key = SortKey::new("55454b3f2f0455294b04393939011101e0c1e0c3dcdcdc");
+    /// let mut query = Query::new();
+    /// 
+    /// query.category_members()
+    ///     .cm_title("Category:War")
+    ///     .cm_start_hex_sort_key(&sort_key);
+    /// ```
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct SortKey(String);
+
+    impl SortKey
+    {
+        pub fn new<S: Into<String>>(hex: S) -> SortKey
+        {
+            SortKey(hex.into())
+        }
+
+        pub fn as_hex(&self) -> &str
+        {
+            &self.0
+        }
+
+        /// Decodes the key into its raw bytes.
+        /// 
+        /// Returns `None` if the key isn't valid hexadecimal.
+        pub fn to_bytes(&self) -> Option<Vec<u8>>
+        {
+            (0..self.0.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(self.0.get(i..i + 2)?, 16).ok())
+                .collect()
+        }
+    }
+
+    impl fmt::Display for SortKey
+    {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+        {
+            f.write_str(&self.0)
+        }
+    }
+
+    impl From<SortKey> for String
+    {
+        fn from(key: SortKey) -> String
+        {
+            key.0
+        }
+    }
+
+    impl From<&SortKey> for String
+    {
+        fn from(key: &SortKey) -> String
+        {
+            key.0.clone()
+        }
     }
 }
 
@@ -100,62 +522,161 @@ pub mod pages
 {
     use super::*;
 
-    #[derive(Debug, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct Data
     {
         // Default data
-        pub ns: u32,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub ns: Option<i32>,
         pub title: String,
+        #[serde(rename="pageid", skip_serializing_if="Option::is_none")]
+        pub page_id: Option<u64>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub missing: Option<bool>,
-        #[serde(rename="pageid")]
-        pub page_id: u64,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub known: Option<bool>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub invalid: Option<bool>,
+        #[serde(rename="invalidreason", skip_serializing_if="Option::is_none")]
+        pub invalid_reason: Option<String>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub special: Option<bool>,
 
         // -----
         // Data from the description prop
         // -----
+        #[serde(skip_serializing_if="Option::is_none")]
         pub description: Option<String>,
-        #[serde(rename="descriptionsource")]
+        #[serde(rename="descriptionsource", skip_serializing_if="Option::is_none")]
         pub description_source: Option<String>,
 
         // -----
         // Data from the extracts prop
         // -----
+        #[serde(skip_serializing_if="Option::is_none")]
         pub extract: Option<String>,
 
         // -----
         // Data from the info prop
         // -----
-        #[serde(rename="contentmodel")]
+        #[serde(rename="contentmodel", skip_serializing_if="Option::is_none")]
         pub content_model: Option<String>,
-        #[serde(rename="pagelanguage")]
+        #[serde(rename="pagelanguage", skip_serializing_if="Option::is_none")]
         pub page_language: Option<String>,
-        #[serde(rename="pagelanguagehtmlcode")]
+        #[serde(rename="pagelanguagehtmlcode", skip_serializing_if="Option::is_none")]
         pub page_language_html_code: Option<String>,
-        #[serde(rename="pagelanguagedir")]
+        #[serde(rename="pagelanguagedir", skip_serializing_if="Option::is_none")]
         pub page_language_dir: Option<String>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub touched: Option<String>,
-        #[serde(rename="lastrevid")]
+        #[serde(rename="lastrevid", skip_serializing_if="Option::is_none")]
         pub last_rev_id: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub length: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub protection: Option<Vec<info::Protection>>,
-        #[serde(rename="restrictiontypes")]
+        #[serde(rename="restrictiontypes", skip_serializing_if="Option::is_none")]
         pub restriction_types: Option<Vec<String>>,
-        #[serde(rename="fullurl")]
+        #[serde(rename="fullurl", skip_serializing_if="Option::is_none")]
         pub full_url: Option<String>,
-        #[serde(rename="editurl")]
+        #[serde(rename="editurl", skip_serializing_if="Option::is_none")]
         pub edit_url: Option<String>,
-        #[serde(rename="canonicalurl")]
+        #[serde(rename="canonicalurl", skip_serializing_if="Option::is_none")]
         pub canonical_url: Option<String>,
-        #[serde(rename="displaytitle")]
+        #[serde(rename="displaytitle", skip_serializing_if="Option::is_none")]
         pub display_title: Option<String>,
+        #[serde(skip_serializing_if="Option::is_none")]
         pub actions: Option<HashMap<String, Vec<info::Actions>>>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub redirect: Option<bool>,
+        #[serde(flatten)]
+        pub extra: Extra,
+    }
+
+    /// The state of a page as reported by the api.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum PageStatus
+    {
+        Exists,
+        Missing,
+        Invalid { reason: String },
+        Special,
+        Redirect,
+    }
+
+    impl Data
+    {
+        /// Derives the [`PageStatus`] from the page flags.
+        /// 
+        /// Pages that are missing but `known` (e.g. files from a shared repository)
+        /// count as existing. The `redirect` flag is only returned with the info prop.
+        pub fn status(&self) -> PageStatus
+        {
+            let flag = |f: Option<bool>| f.unwrap_or(false);
+
+            if flag(self.invalid)
+            {
+                PageStatus::Invalid {
+                    reason: self.invalid_reason.clone().unwrap_or_default()
+                }
+            }
+            else if flag(self.special)
+            {
+                PageStatus::Special
+            }
+            else if flag(self.missing) && !flag(self.known)
+            {
+                PageStatus::Missing
+            }
+            else if flag(self.redirect)
+            {
+                PageStatus::Redirect
+            }
+            else
+            {
+                PageStatus::Exists
+            }
+        }
+
+        /// Fills the fields missing from this page with those of `other`.
+        /// 
+        /// Used to combine the parts of a page returned by different props.
+        /// Fields that are already set are kept.
+        pub fn merge(&mut self, other: Data)
+        {
+            macro_rules! merge_fields
+            {
+                ( $( $field:ident ),* ) =>
+                {
+                    $(
+                        if self.$field.is_none()
+                        {
+                            self.$field = other.$field;
+                        }
+                    )*
+                }
+            }
+
+            merge_fields!(
+                ns, page_id, missing, known, invalid, invalid_reason, special,
+                description, description_source, extract,
+                content_model, page_language, page_language_html_code, page_language_dir,
+                touched, last_rev_id, length, protection, restriction_types,
+                full_url, edit_url, canonical_url, display_title, actions, redirect
+            );
+
+            for (key, value) in other.extra
+            {
+                self.extra.entry(key).or_insert(value);
+            }
+        }
     }
 
     pub mod info
     {
         use super::*;
         
-        #[derive(Debug, Deserialize)]
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
         #[serde(untagged)]
         pub enum Actions
         {
@@ -163,20 +684,24 @@ pub mod pages
             Detailed(DetailedActions),
         }
 
-        #[derive(Debug, Deserialize)]
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
         pub struct DetailedActions
         {
             code: String,
             text: String,
+            #[serde(flatten)]
+            pub extra: Extra,
         }
 
-        #[derive(Debug, Deserialize)]
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
         pub struct Protection
         {
             #[serde(rename="type")]
             protection_type: String,
             level: String,
             expiry: String,
+            #[serde(flatten)]
+            pub extra: Extra,
         }
     }
 }
@@ -185,7 +710,13 @@ pub mod pages
 mod test
 {
     use serde_json;
-    use super::Query;
+    use serde::{Serialize, de::DeserializeOwned};
+    use std::fmt::Debug;
+    use super::{Query, borrowed, parse};
+    use super::pages::PageStatus;
+    use crate::error::{Error, WikiError};
+    use super::all_categories::{self, CategorySize};
+    use super::category_members::PageType;
     
     #[test]
     fn test_deserialize_all_categories_response() {
@@ -203,11 +734,129 @@ mod test
         assert!(query.query.category_members.is_some())
     }
 
+    #[test]
+    fn test_deserialize_typed_category_fields() {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"allcategories\":[{\"category\":\"Hidden things\",\"size\":3,\"pages\":1,\"files\":0,\"subcats\":2,\"hidden\":true},{\"category\":\"Lists\",\"size\":29,\"pages\":1,\"files\":0,\"subcats\":28}],\"categorymembers\":[{\"pageid\":37703894,\"ns\":0,\"title\":\"Lists of colors\",\"sortkey\":\"0403063f39\",\"type\":\"page\"},{\"pageid\":1,\"ns\":14,\"title\":\"Category:Colors\",\"type\":\"subcat\"}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let categories = query.query.all_categories.unwrap();
+        let total: CategorySize = categories.iter()
+            .filter_map(all_categories::Data::size_summary)
+            .sum();
+
+        assert!(categories[0].is_hidden());
+        assert!(!categories[1].is_hidden());
+        assert_eq!(total, CategorySize { size: 32, pages: 2, files: 0, subcats: 30 });
+
+        let members = query.query.category_members.unwrap();
+        let sort_key = members[0].sort_key.as_ref().unwrap();
+
+        assert_eq!(members[0].page_type, Some(PageType::Page));
+        assert_eq!(members[1].page_type, Some(PageType::Subcat));
+        assert_eq!(sort_key.to_bytes(), Some(vec![0x04, 0x03, 0x06, 0x3f, 0x39]));
+        assert_eq!(String::from(sort_key), "0403063f39");
+    }
+
+    #[test]
+    fn test_sum_category_sizes_saturates() {
+        let large = CategorySize { size: u32::MAX - 1, pages: 1, files: 0, subcats: u32::MAX - 1 };
+        let total: CategorySize = vec![large, large, CategorySize { size: 1, pages: 1, files: 1, subcats: 0 }]
+            .into_iter()
+            .sum();
+
+        assert_eq!(total, CategorySize { size: u32::MAX, pages: 3, files: 1, subcats: u32::MAX });
+    }
+
     #[test]
     fn test_deserialize_response_with_warnings() {
         let resp = "{\"batchcomplete\":true,\"warnings\":{\"categorymembers\":{\"warnings\":\"Unrecognized value for parameter \\\"cmprop\\\": I_am_bad_prop.\\nUnrecognized value for parameter \\\"cmtype\\\": I_am_bad_type.\"}},\"query\":{\"categorymembers\":[]}}";
         let query: Query = serde_json::from_str(&resp).unwrap();
 
-        assert!(query.warnings.unwrap().category_members.is_some());
+        assert!(query.warnings.as_ref().unwrap().category_members.is_some());
+        assert_eq!(query.warnings.unwrap().messages(), vec![
+            ("categorymembers", "Unrecognized value for parameter \"cmprop\": I_am_bad_prop."),
+            ("categorymembers", "Unrecognized value for parameter \"cmtype\": I_am_bad_type."),
+        ]);
+    }
+
+    #[test]
+    fn test_deserialize_page_statuses() {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"normalized\":[{\"fromencoded\":false,\"from\":\"main_page\",\"to\":\"Main page\"}],\"pages\":[{\"ns\":0,\"title\":\"Main page\",\"pageid\":217225,\"contentmodel\":\"wikitext\",\"redirect\":true},{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true},{\"title\":\"Bad[title\",\"invalidreason\":\"The requested page title contains invalid characters: \\\"[\\\".\",\"invalid\":true},{\"ns\":-1,\"title\":\"Special:Random\",\"special\":true},{\"ns\":6,\"title\":\"File:Example.jpg\",\"missing\":true,\"known\":true},{\"ns\":0,\"title\":\"Death\",\"pageid\":8221}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let statuses: Vec<PageStatus> = query.query.pages.unwrap()
+            .iter()
+            .map(|page| page.status())
+            .collect();
+
+        assert_eq!(statuses, vec![
+            PageStatus::Redirect,
+            PageStatus::Missing,
+            PageStatus::Invalid { reason: "The requested page title contains invalid characters: \"[\".".to_string() },
+            PageStatus::Special,
+            PageStatus::Exists,
+            PageStatus::Exists,
+        ]);
+        assert_eq!(query.query.normalized.unwrap()[0].to, "Main page");
     }
-}
\ No newline at end of file
+
+    fn assert_round_trip<T>(body: &str) -> String
+        where T: Serialize + DeserializeOwned + PartialEq + Debug
+    {
+        let parsed: T = serde_json::from_str(body).unwrap();
+        let serialized = serde_json::to_string(&parsed).unwrap();
+        let reparsed: T = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(parsed, reparsed);
+
+        serialized
+    }
+
+    #[test]
+    fn test_round_trip_query() {
+        let resp = "{\"batchcomplete\":true,\"curtimestamp\":\"2019-10-01T00:00:00Z\",\"continue\":{\"accontinue\":\"Lists_of_flags\",\"cmcontinue\":\"page|2d45|123\",\"continue\":\"-||\"},\"warnings\":{\"main\":{\"warnings\":\"Unrecognized parameter: bad.\"},\"extracts\":{\"warnings\":\"\\\"exlimit\\\" was too large.\"}},\"query\":{\"normalized\":[{\"fromencoded\":false,\"from\":\"death\",\"to\":\"Death\"}],\"allcategories\":[{\"category\":\"Lists\",\"size\":29,\"pages\":1,\"files\":0,\"subcats\":28,\"hidden\":true}],\"categorymembers\":[{\"pageid\":37703894,\"ns\":0,\"title\":\"Lists of colors\",\"sortkey\":\"0403063f39\",\"sortkeyprefix\":\" \",\"type\":\"page\",\"timestamp\":\"2019-01-30T18:32:56Z\"}],\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"contentmodel\":\"wikitext\",\"lastrevid\":1,\"protection\":[{\"type\":\"edit\",\"level\":\"autoconfirmed\",\"expiry\":\"infinity\"}],\"restrictiontypes\":[\"edit\",\"move\"],\"actions\":{\"edit\":[{\"code\":\"protectedpage\",\"text\":\"This page has been protected.\",\"module\":\"main\"}],\"read\":[]},\"description\":\"permanent cessation of vital functions\",\"extract\":\"Death is...\",\"pagesize\":5},{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true}]}}";
+        let serialized = assert_round_trip::<Query>(resp);
+
+        for name in &["\"batchcomplete\"", "\"accontinue\"", "\"cmcontinue\"", "\"pageid\"", "\"sortkey\"", "\"sortkeyprefix\"", "\"allcategories\"", "\"categorymembers\"", "\"curtimestamp\"", "\"pagesize\""]
+        {
+            assert!(serialized.contains(name), "missing {} in {}", name, serialized);
+        }
+
+        assert!(!serialized.contains("null"));
+
+        let borrowed: borrowed::Query = serde_json::from_str(resp).unwrap();
+        let serialized = serde_json::to_string(&borrowed).unwrap();
+
+        assert_eq!(borrowed, serde_json::from_str(&serialized).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip_error() {
+        let resp = "{\"error\":{\"code\":\"badvalue\",\"info\":\"Unrecognized value for parameter \\\"list\\\": bad.\",\"docref\":\"See https://en.wikipedia.org/w/api.php for API usage.\"},\"servedby\":\"mw1234\"}";
+        let serialized = assert_round_trip::<WikiError>(resp);
+
+        assert!(serialized.contains("\"servedby\""));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let resp = "{\"error\":{\"code\":\"badvalue\",\"info\":\"Unrecognized value.\",\"docref\":\"See api.php.\"},\"warnings\":{\"main\":{\"warnings\":\"Unrecognized parameter: bad.\"}},\"servedby\":\"mw1234\"}";
+        let legacy = "{\"error\":{\"code\":\"badvalue\",\"info\":\"Unrecognized value.\",\"*\":\"See api.php.\"},\"servedby\":\"mw1234\"}";
+
+        for (body, legacy) in &[(resp, false), (legacy, true)]
+        {
+            match parse(body.as_bytes(), *legacy)
+            {
+                Err(Error::Api(err)) => {
+                    assert_eq!(err.error.code, "badvalue");
+                    assert_eq!(err.error.docref, "See api.php.");
+                },
+                other => panic!("expected an api error, got {:?}", other),
+            }
+        }
+
+        let query = parse(b"{\"batchcomplete\":true,\"query\":{\"categorymembers\":[]},\"servedby\":\"mw1234\"}", false).unwrap();
+
+        assert!(query.extra.contains_key("servedby"));
+    }
+}
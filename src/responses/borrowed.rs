@@ -0,0 +1,253 @@
+//! Borrowed variants of the high volume response types.
+//!
+//! String fields are [`Cow`]s that borrow from the response body when the
+//! json string has no escapes, so large listings can be parsed without
+//! copying every title and extract. Unlike the owned types, unknown fields
+//! are skipped rather than kept in an `extra` map.
+//!
+//! Use `into_owned` to turn a record into its owned counterpart.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::responses::borrowed;
+//!
+//! let body = br#"{"batchcomplete":true,"query":{"categorymembers":[{"pageid":1,"ns":0,"title":"War"}]}}"#;
+//! let response: borrowed::Query = serde_json::from_slice(body).unwrap();
+//!
+//! let members = response.query.category_members.unwrap();
+//! assert_eq!(members[0].title.as_deref(), Some("War"));
+//! ```
+//!
+//! [`Cow`]: https://doc.rust-lang.org/std/borrow/enum.Cow.html
+
+use std::borrow::Cow;
+
+use super::*;
+
+fn owned(value: Option<Cow<str>>) -> Option<String>
+{
+    value.map(Cow::into_owned)
+}
+
+// Serde only borrows a bare `Cow<str>`, so optional strings go through a newtype.
+fn borrow_str<'de: 'a, 'a, D>(deserializer: D) -> Result<Option<Cow<'a, str>>, D::Error>
+    where D: serde::Deserializer<'de>
+{
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+
+    Option::<Borrowed>::deserialize(deserializer)
+        .map(|value| value.map(|b| b.0))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryBlock<'a>
+{
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub normalized: Option<Vec<Normalized>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub redirects: Option<Vec<Redirect>>,
+    #[serde(borrow, skip_serializing_if="Option::is_none")]
+    pub pages: Option<Vec<pages::Data<'a>>>,
+    #[serde(rename="allcategories", skip_serializing_if="Option::is_none")]
+    pub all_categories: Option<Vec<all_categories::Data>>,
+    #[serde(borrow, rename="categorymembers", skip_serializing_if="Option::is_none")]
+    pub category_members: Option<Vec<category_members::Data<'a>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Query<'a>
+{
+    #[serde(rename = "batchcomplete", default)]
+    pub batch_complete: bool,
+    #[serde(borrow)]
+    pub query: QueryBlock<'a>,
+    #[serde(rename = "continue", skip_serializing_if="Option::is_none")]
+    pub continue_block: Option<ContinueBlock>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub warnings: Option<WarningBlock>,
+}
+
+pub mod category_members
+{
+    use super::*;
+
+    use crate::responses::category_members::{self as owned_members, PageType, SortKey};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Data<'a>
+    {
+        #[serde(rename="pageid", skip_serializing_if="Option::is_none")]
+        pub page_id: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub ns: Option<u32>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="sortkey", skip_serializing_if="Option::is_none")]
+        pub sort_key: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="sortkeyprefix", skip_serializing_if="Option::is_none")]
+        pub sort_key_prefix: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", skip_serializing_if="Option::is_none")]
+        pub title: Option<Cow<'a, str>>,
+        #[serde(rename="type", skip_serializing_if="Option::is_none")]
+        pub page_type: Option<PageType>,
+        #[serde(borrow, default, deserialize_with="borrow_str", skip_serializing_if="Option::is_none")]
+        pub timestamp: Option<Cow<'a, str>>,
+    }
+
+    impl<'a> Data<'a>
+    {
+        pub fn into_owned(self) -> owned_members::Data
+        {
+            owned_members::Data {
+                page_id: self.page_id,
+                ns: self.ns,
+                sort_key: self.sort_key.map(SortKey::new),
+                sort_key_prefix: owned(self.sort_key_prefix),
+                title: owned(self.title),
+                page_type: self.page_type,
+                timestamp: owned(self.timestamp),
+                extra: Extra::new(),
+            }
+        }
+    }
+}
+
+pub mod pages
+{
+    use super::*;
+
+    use crate::responses::pages::{self as owned_pages, info};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Data<'a>
+    {
+        // Default data
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub ns: Option<i32>,
+        #[serde(borrow)]
+        pub title: Cow<'a, str>,
+        #[serde(rename="pageid", skip_serializing_if="Option::is_none")]
+        pub page_id: Option<u64>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub missing: Option<bool>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub known: Option<bool>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub invalid: Option<bool>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="invalidreason", skip_serializing_if="Option::is_none")]
+        pub invalid_reason: Option<Cow<'a, str>>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub special: Option<bool>,
+
+        // -----
+        // Data from the description prop
+        // -----
+        #[serde(borrow, default, deserialize_with="borrow_str", skip_serializing_if="Option::is_none")]
+        pub description: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="descriptionsource", skip_serializing_if="Option::is_none")]
+        pub description_source: Option<Cow<'a, str>>,
+
+        // -----
+        // Data from the extracts prop
+        // -----
+        #[serde(borrow, default, deserialize_with="borrow_str", skip_serializing_if="Option::is_none")]
+        pub extract: Option<Cow<'a, str>>,
+
+        // -----
+        // Data from the info prop
+        // -----
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="contentmodel", skip_serializing_if="Option::is_none")]
+        pub content_model: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="pagelanguage", skip_serializing_if="Option::is_none")]
+        pub page_language: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="pagelanguagehtmlcode", skip_serializing_if="Option::is_none")]
+        pub page_language_html_code: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="pagelanguagedir", skip_serializing_if="Option::is_none")]
+        pub page_language_dir: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", skip_serializing_if="Option::is_none")]
+        pub touched: Option<Cow<'a, str>>,
+        #[serde(rename="lastrevid", skip_serializing_if="Option::is_none")]
+        pub last_rev_id: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub length: Option<u32>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub protection: Option<Vec<info::Protection>>,
+        #[serde(rename="restrictiontypes", skip_serializing_if="Option::is_none")]
+        pub restriction_types: Option<Vec<String>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="fullurl", skip_serializing_if="Option::is_none")]
+        pub full_url: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="editurl", skip_serializing_if="Option::is_none")]
+        pub edit_url: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="canonicalurl", skip_serializing_if="Option::is_none")]
+        pub canonical_url: Option<Cow<'a, str>>,
+        #[serde(borrow, default, deserialize_with="borrow_str", rename="displaytitle", skip_serializing_if="Option::is_none")]
+        pub display_title: Option<Cow<'a, str>>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub actions: Option<HashMap<String, Vec<info::Actions>>>,
+        #[serde(skip_serializing_if="Option::is_none")]
+        pub redirect: Option<bool>,
+    }
+
+    impl<'a> Data<'a>
+    {
+        pub fn into_owned(self) -> owned_pages::Data
+        {
+            owned_pages::Data {
+                ns: self.ns,
+                title: self.title.into_owned(),
+                page_id: self.page_id,
+                missing: self.missing,
+                known: self.known,
+                invalid: self.invalid,
+                invalid_reason: owned(self.invalid_reason),
+                special: self.special,
+                description: owned(self.description),
+                description_source: owned(self.description_source),
+                extract: owned(self.extract),
+                content_model: owned(self.content_model),
+                page_language: owned(self.page_language),
+                page_language_html_code: owned(self.page_language_html_code),
+                page_language_dir: owned(self.page_language_dir),
+                touched: owned(self.touched),
+                last_rev_id: self.last_rev_id,
+                length: self.length,
+                protection: self.protection,
+                restriction_types: self.restriction_types,
+                full_url: owned(self.full_url),
+                edit_url: owned(self.edit_url),
+                canonical_url: owned(self.canonical_url),
+                display_title: owned(self.display_title),
+                actions: self.actions,
+                redirect: self.redirect,
+                extra: Extra::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod borrowed_tests
+{
+    use super::*;
+
+    #[test]
+    fn borrows_unescaped_strings()
+    {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"categorymembers\":[{\"pageid\":435914,\"ns\":0,\"title\":\"List of colors: A\\u2013F\",\"sortkey\":\"2d0f0303063f\",\"sortkeyprefix\":\"C01\",\"type\":\"page\",\"timestamp\":\"2018-11-01T14:19:47Z\"}],\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"extract\":\"Death is the permanent cessation of all biological...\"}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let member = query.query.category_members.unwrap().remove(0);
+        let page = query.query.pages.unwrap().remove(0);
+
+        assert!(matches!(member.sort_key, Some(Cow::Borrowed(_))));
+        assert!(matches!(member.title, Some(Cow::Owned(_))));
+        assert!(matches!(page.extract, Some(Cow::Borrowed(_))));
+
+        let member = member.into_owned();
+        let page = page.into_owned();
+
+        assert_eq!(member.title.as_deref(), Some("List of colors: A–F"));
+        assert_eq!(member.sort_key.unwrap().as_hex(), "2d0f0303063f");
+        assert_eq!(page.page_id, Some(8221));
+        assert_eq!(page.extract.as_deref(), Some("Death is the permanent cessation of all biological..."));
+    }
+}
@@ -0,0 +1,426 @@
+//! Collects unknown and missing response fields over a session.
+//!
+//! Feed every response to a [`DriftReport`]. Fields the response types don't
+//! model are counted as unknown. Fields registered with
+//! [`DriftReport::expect`] are counted as missing whenever a record lacks them.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::responses::{self, drift::DriftReport};
+//!
+//! let mut report = DriftReport::new();
+//! report.expect("pages::Data", "touched");
+//!
+//! # let body = "{\"batchcomplete\":true,\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"lastrevid\":1}]}}";
+//! let response: responses::Query = serde_json::from_str(body).unwrap();
+//! report.observe(&response);
+//!
+//! if !report.is_empty()
+//! {
+//!     eprintln!("{}", report);
+//! }
+//! ```
+//!
+//! [`DriftReport`]: struct.DriftReport.html
+//! [`DriftReport::expect`]: struct.DriftReport.html#method.expect
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::*;
+use crate::error::{WikiError, WikiErrorInner};
+
+/// A response record whose fields can be checked for drift.
+pub trait Fields
+{
+    /// The name the record is reported under.
+    const NAME: &'static str;
+
+    /// The fields that weren't modeled.
+    fn extra(&self) -> &Extra;
+
+    /// The wire names of the fields that were returned. Required fields are
+    /// always present.
+    fn present(&self) -> Vec<&'static str>;
+}
+
+macro_rules! impl_fields
+{
+    ( $type:ty, $name:expr, [ $( $field:ident => $wire:expr ),* ] ) =>
+    {
+        impl_fields!($type, $name, [ $( $field => $wire ),* ], []);
+    };
+    ( $type:ty, $name:expr, [ $( $field:ident => $wire:expr ),* ], [ $( $required:expr ),* ] ) =>
+    {
+        impl Fields for $type
+        {
+            const NAME: &'static str = $name;
+
+            fn extra(&self) -> &Extra
+            {
+                &self.extra
+            }
+
+            #[allow(unused_mut)]
+            fn present(&self) -> Vec<&'static str>
+            {
+                let mut present = vec![$( $required ),*];
+
+                $(
+                    if self.$field.is_some()
+                    {
+                        present.push($wire);
+                    }
+                )*
+
+                present
+            }
+        }
+    }
+}
+
+impl_fields!(Query, "Query", [continue_block => "continue", warnings => "warnings"]);
+impl_fields!(QueryBlock, "QueryBlock", [
+    normalized => "normalized",
+    redirects => "redirects",
+    pages => "pages",
+    all_categories => "allcategories",
+    category_members => "categorymembers",
+    user_info => "userinfo"
+]);
+impl_fields!(WarningBlock, "WarningBlock", [
+    all_categories => "allcategories",
+    category_members => "categorymembers",
+    info => "info",
+    pages => "pages",
+    description => "description",
+    extracts => "extracts"
+]);
+impl_fields!(Warnings, "Warnings", [], ["warnings"]);
+impl_fields!(Normalized, "Normalized", [], ["from", "to"]);
+impl_fields!(Redirect, "Redirect", [to_fragment => "tofragment"], ["from", "to"]);
+impl_fields!(UserInfo, "UserInfo", [anon => "anon", rights => "rights"], ["id", "name"]);
+impl_fields!(all_categories::Data, "all_categories::Data", [
+    size => "size",
+    pages => "pages",
+    files => "files",
+    subcats => "subcats",
+    hidden => "hidden"
+], ["category"]);
+impl_fields!(category_members::Data, "category_members::Data", [
+    page_id => "pageid",
+    ns => "ns",
+    sort_key => "sortkey",
+    sort_key_prefix => "sortkeyprefix",
+    title => "title",
+    page_type => "type",
+    timestamp => "timestamp"
+]);
+impl_fields!(pages::Data, "pages::Data", [
+    ns => "ns",
+    page_id => "pageid",
+    missing => "missing",
+    known => "known",
+    invalid => "invalid",
+    invalid_reason => "invalidreason",
+    special => "special",
+    description => "description",
+    description_source => "descriptionsource",
+    extract => "extract",
+    content_model => "contentmodel",
+    page_language => "pagelanguage",
+    page_language_html_code => "pagelanguagehtmlcode",
+    page_language_dir => "pagelanguagedir",
+    touched => "touched",
+    last_rev_id => "lastrevid",
+    length => "length",
+    protection => "protection",
+    restriction_types => "restrictiontypes",
+    full_url => "fullurl",
+    edit_url => "editurl",
+    canonical_url => "canonicalurl",
+    display_title => "displaytitle",
+    actions => "actions",
+    redirect => "redirect"
+], ["title"]);
+impl_fields!(pages::info::Protection, "pages::info::Protection", [], ["type", "level", "expiry"]);
+impl_fields!(pages::info::DetailedActions, "pages::info::DetailedActions", [], ["code", "text"]);
+impl_fields!(WikiError, "WikiError", [], ["error", "servedby"]);
+impl_fields!(WikiErrorInner, "WikiErrorInner", [], ["code", "info", "docref"]);
+
+#[derive(Debug, Default)]
+struct RecordStats
+{
+    seen: usize,
+    unknown: BTreeMap<String, usize>,
+    present: BTreeMap<&'static str, usize>,
+}
+
+/// A field that was returned but isn't modeled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownField
+{
+    pub record: &'static str,
+    pub field: String,
+    /// The number of records the field was seen in.
+    pub count: usize,
+}
+
+/// An expected field that some records were missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingField
+{
+    pub record: &'static str,
+    pub field: &'static str,
+    /// The number of records without the field.
+    pub missing: usize,
+    /// The number of records observed.
+    pub seen: usize,
+}
+
+/// Unknown and missing fields seen over a session.
+#[derive(Debug, Default)]
+pub struct DriftReport
+{
+    records: BTreeMap<&'static str, RecordStats>,
+    expected: BTreeSet<(&'static str, &'static str)>,
+}
+
+impl DriftReport
+{
+    pub fn new() -> DriftReport
+    {
+        DriftReport::default()
+    }
+
+    /// Marks a field as relied upon.
+    ///
+    /// `record` is a [`Fields::NAME`], such as `"pages::Data"`, and `field` is
+    /// the wire name of the field, such as `"lastrevid"`.
+    ///
+    /// [`Fields::NAME`]: trait.Fields.html#associatedconstant.NAME
+    pub fn expect(&mut self, record: &'static str, field: &'static str) -> &mut Self
+    {
+        self.expected.insert((record, field));
+        self
+    }
+
+    /// Records the fields of a single record.
+    pub fn record<T: Fields>(&mut self, record: &T)
+    {
+        let stats = self.records.entry(T::NAME).or_default();
+
+        stats.seen += 1;
+
+        for key in record.extra().keys()
+        {
+            *stats.unknown.entry(key.clone()).or_insert(0) += 1;
+        }
+
+        for field in record.present()
+        {
+            *stats.present.entry(field).or_insert(0) += 1;
+        }
+    }
+
+    /// Records every record in a query response.
+    pub fn observe(&mut self, query: &Query)
+    {
+        self.record(query);
+        self.record(&query.query);
+
+        if let Some(warnings) = &query.warnings
+        {
+            self.record(warnings);
+
+            let modules = [
+                &warnings.all_categories,
+                &warnings.category_members,
+                &warnings.info,
+                &warnings.pages,
+                &warnings.description,
+                &warnings.extracts,
+            ];
+
+            for module in modules.iter().filter_map(|m| m.as_ref())
+            {
+                self.record(module);
+            }
+        }
+
+        for normalized in query.query.normalized.iter().flatten()
+        {
+            self.record(normalized);
+        }
+
+        for redirect in query.query.redirects.iter().flatten()
+        {
+            self.record(redirect);
+        }
+
+        if let Some(user_info) = &query.query.user_info
+        {
+            self.record(user_info);
+        }
+
+        for category in query.query.all_categories.iter().flatten()
+        {
+            self.record(category);
+        }
+
+        for member in query.query.category_members.iter().flatten()
+        {
+            self.record(member);
+        }
+
+        for page in query.query.pages.iter().flatten()
+        {
+            self.record(page);
+
+            for protection in page.protection.iter().flatten()
+            {
+                self.record(protection);
+            }
+
+            for actions in page.actions.iter().flat_map(|a| a.values()).flatten()
+            {
+                if let pages::info::Actions::Detailed(detailed) = actions
+                {
+                    self.record(detailed);
+                }
+            }
+        }
+    }
+
+    /// Records an error response.
+    pub fn observe_error(&mut self, error: &WikiError)
+    {
+        self.record(error);
+        self.record(&error.error);
+    }
+
+    pub fn unknown_fields(&self) -> Vec<UnknownField>
+    {
+        self.records.iter()
+            .flat_map(|(record, stats)| {
+                stats.unknown.iter().map(move |(field, count)| UnknownField {
+                    record,
+                    field: field.clone(),
+                    count: *count,
+                })
+            })
+            .collect()
+    }
+
+    /// Lists the expected fields that were missing from at least one record.
+    ///
+    /// Records that were never observed aren't reported.
+    pub fn missing_fields(&self) -> Vec<MissingField>
+    {
+        self.expected.iter()
+            .filter_map(|(record, field)| {
+                let stats = self.records.get(record)?;
+                let present = stats.present.get(field).copied().unwrap_or(0);
+
+                if present < stats.seen
+                {
+                    Some(MissingField {
+                        record,
+                        field,
+                        missing: stats.seen - present,
+                        seen: stats.seen,
+                    })
+                }
+                else
+                {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Whether no unknown or missing fields were seen.
+    pub fn is_empty(&self) -> bool
+    {
+        self.records.values().all(|stats| stats.unknown.is_empty())
+            && self.missing_fields().is_empty()
+    }
+}
+
+impl fmt::Display for DriftReport
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        for unknown in self.unknown_fields()
+        {
+            writeln!(f, "unknown field {}.{} in {} record(s)", unknown.record, unknown.field, unknown.count)?;
+        }
+
+        for missing in self.missing_fields()
+        {
+            writeln!(f, "missing field {}.{} in {} of {} record(s)", missing.record, missing.field, missing.missing, missing.seen)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod drift_tests
+{
+    use super::*;
+
+    #[test]
+    fn reports_unknown_and_missing_fields()
+    {
+        let resp = "{\"batchcomplete\":true,\"curtimestamp\":\"2019-10-01T00:00:00Z\",\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"touched\":\"2019-09-30T00:00:00Z\",\"lastrevid\":1,\"pagesize\":5},{\"ns\":0,\"title\":\"Life\",\"pageid\":8222,\"lastrevid\":2,\"pagesize\":6}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let mut report = DriftReport::new();
+
+        report.expect("pages::Data", "touched")
+            .expect("pages::Data", "lastrevid")
+            .expect("category_members::Data", "title");
+
+        report.observe(&query);
+
+        assert!(!report.is_empty());
+        assert_eq!(report.unknown_fields(), vec![
+            UnknownField { record: "Query", field: "curtimestamp".to_string(), count: 1 },
+            UnknownField { record: "pages::Data", field: "pagesize".to_string(), count: 2 },
+        ]);
+        assert_eq!(report.missing_fields(), vec![
+            MissingField { record: "pages::Data", field: "touched", missing: 1, seen: 2 },
+        ]);
+    }
+
+    #[test]
+    fn empty_without_drift()
+    {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"allcategories\":[{\"category\":\"Lists\",\"size\":29,\"pages\":1,\"files\":0,\"subcats\":28}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let mut report = DriftReport::new();
+
+        report.expect("all_categories::Data", "size");
+        report.observe(&query);
+
+        assert!(report.is_empty());
+        assert_eq!(report.to_string(), "");
+    }
+
+    #[test]
+    fn required_fields_are_never_missing()
+    {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"allcategories\":[{\"category\":\"Lists\"}],\"pages\":[{\"ns\":0,\"title\":\"Death\"}]}}";
+        let query: Query = serde_json::from_str(resp).unwrap();
+
+        let mut report = DriftReport::new();
+
+        report.expect("all_categories::Data", "category")
+            .expect("pages::Data", "title");
+        report.observe(&query);
+
+        assert_eq!(report.missing_fields(), vec![]);
+    }
+}
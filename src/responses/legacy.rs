@@ -0,0 +1,168 @@
+//! Parses `formatversion=1` responses from older mediawiki installs.
+//!
+//! Mediawiki before 1.25 only speaks format version 1, where:
+//! - `pages` is an object keyed by page id instead of an array.
+//! - Boolean flags are `""` when true and left out when false.
+//! - Text content, like warnings and error docrefs, sits under a `*` key.
+//!
+//! The functions here rewrite such a response into its `formatversion=2`
+//! shape, then deserialize it into the usual response types.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::requests::Query;
+//! use wikiquery::responses::{self, legacy};
+//!
+//! let mut query = Query::new();
+//!
+//! query.format_version("1")
+//!     .pages()
+//!     .titles("Death");
+//!
+//! /*
+//!     Send the request and receive the body
+//! */
+//! # let body = r#"{"batchcomplete":"","query":{"pages":{"8221":{"pageid":8221,"ns":0,"title":"Death"}}}}"#;
+//!
+//! let response: responses::Query = legacy::from_str(body).unwrap();
+//! assert!(response.batch_complete);
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Keys whose `""` value means `true`.
+const FLAGS: &[&str] = &[
+    "batchcomplete",
+    "missing",
+    "known",
+    "invalid",
+    "special",
+    "redirect",
+    "hidden",
+    "new",
+    "watched",
+    "readable",
+    "anon",
+];
+
+/// Deserializes a `formatversion=1` response.
+pub fn from_str<T: DeserializeOwned>(body: &str) -> serde_json::Result<T>
+{
+    from_value(serde_json::from_str(body)?)
+}
+
+/// Deserializes a `formatversion=1` response.
+pub fn from_slice<T: DeserializeOwned>(body: &[u8]) -> serde_json::Result<T>
+{
+    from_value(serde_json::from_slice(body)?)
+}
+
+/// Deserializes a parsed `formatversion=1` response.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> serde_json::Result<T>
+{
+    serde_json::from_value(normalize(value))
+}
+
+/// Rewrites a `formatversion=1` response into its `formatversion=2` shape.
+pub fn normalize(value: Value) -> Value
+{
+    normalize_value(None, value)
+}
+
+fn normalize_value(parent: Option<&str>, value: Value) -> Value
+{
+    match value
+    {
+        Value::Object(map) => Value::Object(normalize_object(parent, map)),
+        Value::Array(values) => Value::Array(
+            values.into_iter()
+                .map(|v| normalize_value(parent, v))
+                .collect()
+        ),
+        other => other,
+    }
+}
+
+fn normalize_object(parent: Option<&str>, map: Map<String, Value>) -> Map<String, Value>
+{
+    map.into_iter()
+        .map(|(key, value)| {
+            let value = match (key.as_str(), value)
+            {
+                ("pages", Value::Object(pages)) => Value::Array(
+                    pages.into_iter()
+                        .map(|(_, page)| normalize_value(Some("pages"), page))
+                        .collect()
+                ),
+                ("warnings", Value::Object(modules)) => Value::Object(
+                    modules.into_iter()
+                        .map(|(module, warnings)| (module, rename_content(warnings, "warnings")))
+                        .collect()
+                ),
+                ("error", error) => normalize_value(Some("error"), rename_content(error, "docref")),
+                (flag, Value::String(ref s)) if s.is_empty() && is_flag(parent, flag) => Value::Bool(true),
+                (_, value) => normalize_value(Some(&key), value),
+            };
+
+            let key = if key == "*" { "content".to_string() } else { key };
+
+            (key, value)
+        })
+        .collect()
+}
+
+fn rename_content(value: Value, to: &str) -> Value
+{
+    match value
+    {
+        Value::Object(mut map) => {
+            if let Some(content) = map.remove("*")
+            {
+                map.insert(to.to_string(), content);
+            }
+
+            Value::Object(map)
+        },
+        other => other,
+    }
+}
+
+fn is_flag(parent: Option<&str>, key: &str) -> bool
+{
+    parent == Some("actions") || FLAGS.contains(&key)
+}
+
+#[cfg(test)]
+mod legacy_tests
+{
+    use super::*;
+    use crate::error::WikiError;
+    use crate::responses::Query;
+    use crate::responses::pages::PageStatus;
+
+    #[test]
+    fn normalizes_pages_and_flags()
+    {
+        let body = "{\"batchcomplete\":\"\",\"warnings\":{\"info\":{\"*\":\"Unrecognized value for parameter \\\"inprop\\\": bad.\"}},\"query\":{\"normalized\":[{\"from\":\"death\",\"to\":\"Death\"}],\"pages\":{\"-1\":{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":\"\"},\"8221\":{\"pageid\":8221,\"ns\":0,\"title\":\"Death\",\"redirect\":\"\"}}}}";
+        let query: Query = from_str(body).unwrap();
+
+        let pages = query.query.pages.unwrap();
+        let statuses: Vec<_> = pages.iter().map(|page| page.status()).collect();
+
+        assert!(query.batch_complete);
+        assert_eq!(statuses, vec![PageStatus::Missing, PageStatus::Redirect]);
+        assert_eq!(query.warnings.unwrap().info.unwrap().warnings, "Unrecognized value for parameter \"inprop\": bad.");
+        assert_eq!(query.query.normalized.unwrap()[0].to, "Death");
+    }
+
+    #[test]
+    fn normalizes_errors()
+    {
+        let body = "{\"servedby\":\"mw1234\",\"error\":{\"code\":\"badvalue\",\"info\":\"Unrecognized value for parameter \\\"list\\\": bad.\",\"*\":\"See https://en.wikipedia.org/w/api.php for API usage.\"}}";
+        let error: WikiError = from_str(body).unwrap();
+
+        assert_eq!(error.error.code, "badvalue");
+        assert_eq!(error.error.docref, "See https://en.wikipedia.org/w/api.php for API usage.");
+    }
+}
@@ -0,0 +1,161 @@
+//! Combines page data split across continuation responses.
+//!
+//! When several props are requested at once, mediawiki may return a page's
+//! info in one response and its extract in a later one. A [`PageMerger`]
+//! collects the pages of every response in a batch and hands them out, merged
+//! by page id, once a response reports `batchcomplete`.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::requests::Query;
+//! use wikiquery::responses::merge::PageMerger;
+//!
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles("Death|Life")
+//!     .info()
+//!     .extracts()
+//!     .description();
+//!
+//! let mut merger = PageMerger::new();
+//!
+//! loop
+//! {
+//!     let request = query.build().unwrap();
+//!     /*
+//!         Send the request and receive a responses::Query
+//!     */
+//!     # let mut response: wikiquery::responses::Query = serde_json::from_str(r#"{"batchcomplete":true,"query":{"pages":[]}}"#).unwrap();
+//!
+//!     for page in merger.push(&mut response)
+//!     {
+//!         println!("{:?}", page);
+//!     }
+//!
+//!     if response.continue_block.is_none()
+//!     {
+//!         break;
+//!     }
+//!
+//!     query.continue_query(&response.continue_block);
+//! }
+//! ```
+//!
+//! [`PageMerger`]: struct.PageMerger.html
+
+use std::collections::HashMap;
+
+use super::*;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum PageKey
+{
+    Id(u64),
+    Title(String),
+}
+
+impl PageKey
+{
+    // Missing and invalid pages have no id, so they're matched by title.
+    fn of(page: &pages::Data) -> PageKey
+    {
+        match page.page_id
+        {
+            Some(id) => PageKey::Id(id),
+            None => PageKey::Title(page.title.clone()),
+        }
+    }
+}
+
+/// Merges the pages of successive responses until each batch is complete.
+#[derive(Debug, Default)]
+pub struct PageMerger
+{
+    pages: Vec<pages::Data>,
+    index: HashMap<PageKey, usize>,
+}
+
+impl PageMerger
+{
+    pub fn new() -> PageMerger
+    {
+        PageMerger::default()
+    }
+
+    /// Takes the pages out of `response` and merges them with the batch.
+    ///
+    /// Returns the merged pages of the batch, in the order they were first
+    /// seen, if `response` completes it. Otherwise returns nothing.
+    pub fn push(&mut self, response: &mut Query) -> Vec<pages::Data>
+    {
+        for page in response.query.pages.take().into_iter().flatten()
+        {
+            let key = PageKey::of(&page);
+
+            match self.index.get(&key)
+            {
+                Some(&i) => self.pages[i].merge(page),
+                None => {
+                    self.index.insert(key, self.pages.len());
+                    self.pages.push(page);
+                },
+            }
+        }
+
+        if response.batch_complete
+        {
+            self.finish()
+        }
+        else
+        {
+            Vec::new()
+        }
+    }
+
+    /// The number of pages waiting for their batch to complete.
+    pub fn pending(&self) -> usize
+    {
+        self.pages.len()
+    }
+
+    /// Hands out the pending pages even though their batch isn't complete.
+    pub fn finish(&mut self) -> Vec<pages::Data>
+    {
+        self.index.clear();
+        std::mem::take(&mut self.pages)
+    }
+}
+
+#[cfg(test)]
+mod merge_tests
+{
+    use super::*;
+
+    fn response(body: &str) -> Query
+    {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn merges_pages_until_batch_complete()
+    {
+        let mut merger = PageMerger::new();
+
+        let mut first = response("{\"continue\":{\"excontinue\":\"1\",\"continue\":\"||info|description\"},\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"lastrevid\":1,\"description\":\"permanent cessation of vital functions\",\"extract\":\"Death is...\"},{\"ns\":0,\"title\":\"Life\",\"pageid\":8222,\"lastrevid\":2},{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true}]}}");
+        let mut second = response("{\"batchcomplete\":true,\"query\":{\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221},{\"ns\":0,\"title\":\"Life\",\"pageid\":8222,\"extract\":\"Life is...\"},{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true}]}}");
+
+        assert!(merger.push(&mut first).is_empty());
+        assert_eq!(merger.pending(), 3);
+
+        let pages = merger.push(&mut second);
+
+        assert_eq!(merger.pending(), 0);
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0].extract.as_deref(), Some("Death is..."));
+        assert_eq!(pages[0].description.as_deref(), Some("permanent cessation of vital functions"));
+        assert_eq!(pages[1].last_rev_id, Some(2));
+        assert_eq!(pages[1].extract.as_deref(), Some("Life is..."));
+        assert_eq!(pages[2].missing, Some(true));
+    }
+}
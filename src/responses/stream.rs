@@ -0,0 +1,492 @@
+//! Streams the items of a query response one at a time.
+//!
+//! A [`StreamParser`] is fed the body in chunks as they arrive and yields each
+//! page, category or category member as soon as its json object is complete,
+//! so a large response never has to be held in memory. Everything else in the
+//! body is kept, and [`StreamParser::finish`] parses it into a
+//! [`responses::Query`] with empty item lists once the body has ended. This
+//! is where continuation and warnings are read from.
+//!
+//! Only `formatversion=2` responses are supported, where item lists are arrays.
+//!
+//! # Examples
+//! Reading from an [`io::Read`]:
+//! ```
+//! use wikiquery::responses::stream;
+//!
+//! let body: &[u8] = br#"{"batchcomplete":true,"continue":{"cmcontinue":"page|1|2","continue":"-||"},"query":{"categorymembers":[{"pageid":1,"ns":0,"title":"War"}]}}"#;
+//! let mut items = stream::from_reader(body);
+//!
+//! for item in &mut items
+//! {
+//!     let member = item.unwrap().into_category_member().unwrap();
+//!     println!("{:?}", member.title);
+//! }
+//!
+//! let tail = items.finish().unwrap();
+//! assert_eq!(tail.continue_block.unwrap().cm_continue().unwrap(), "page|1|2");
+//! ```
+//!
+//! Feeding chunks from an async body:
+//! ```ignore
+//! let mut parser = StreamParser::new();
+//!
+//! while let Some(chunk) = body.next().await
+//! {
+//!     parser.feed(&chunk?)?;
+//!
+//!     while let Some(item) = parser.next_item()
+//!     {
+//!         handle(item);
+//!     }
+//! }
+//!
+//! let tail = parser.finish()?;
+//! ```
+//!
+//! [`StreamParser`]: struct.StreamParser.html
+//! [`StreamParser::finish`]: struct.StreamParser.html#method.finish
+//! [`responses::Query`]: ../struct.Query.html
+//! [`io::Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+use super::*;
+
+/// A single item of a query response.
+#[derive(Debug)]
+pub enum Item
+{
+    Page(Box<pages::Data>),
+    Category(all_categories::Data),
+    CategoryMember(category_members::Data),
+}
+
+impl Item
+{
+    pub fn into_page(self) -> Option<pages::Data>
+    {
+        match self
+        {
+            Item::Page(page) => Some(*page),
+            _ => None,
+        }
+    }
+
+    pub fn into_category(self) -> Option<all_categories::Data>
+    {
+        match self
+        {
+            Item::Category(category) => Some(category),
+            _ => None,
+        }
+    }
+
+    pub fn into_category_member(self) -> Option<category_members::Data>
+    {
+        match self
+        {
+            Item::CategoryMember(member) => Some(member),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ItemKind
+{
+    Page,
+    Category,
+    CategoryMember,
+}
+
+impl ItemKind
+{
+    fn from_key(key: &[u8]) -> Option<ItemKind>
+    {
+        match key
+        {
+            b"pages" => Some(ItemKind::Page),
+            b"allcategories" => Some(ItemKind::Category),
+            b"categorymembers" => Some(ItemKind::CategoryMember),
+            _ => None,
+        }
+    }
+
+    fn parse(self, bytes: &[u8]) -> serde_json::Result<Item>
+    {
+        Ok(match self
+        {
+            ItemKind::Page => Item::Page(Box::new(serde_json::from_slice(bytes)?)),
+            ItemKind::Category => Item::Category(serde_json::from_slice(bytes)?),
+            ItemKind::CategoryMember => Item::CategoryMember(serde_json::from_slice(bytes)?),
+        })
+    }
+}
+
+enum Frame
+{
+    Object { key: Vec<u8>, expect_key: bool },
+    Array,
+}
+
+struct PendingItem
+{
+    kind: ItemKind,
+    depth: usize,
+    bytes: Vec<u8>,
+}
+
+/// An incremental parser for query responses.
+///
+/// Feed it bytes with [`StreamParser::feed`] and take the completed items with
+/// [`StreamParser::next_item`].
+///
+/// [`StreamParser::feed`]: struct.StreamParser.html#method.feed
+/// [`StreamParser::next_item`]: struct.StreamParser.html#method.next_item
+pub struct StreamParser
+{
+    stack: Vec<Frame>,
+    in_string: bool,
+    escaped: bool,
+    capturing_key: bool,
+    key: Vec<u8>,
+    item: Option<PendingItem>,
+    skeleton: Vec<u8>,
+    ready: VecDeque<Item>,
+}
+
+impl Default for StreamParser
+{
+    fn default() -> StreamParser
+    {
+        StreamParser::new()
+    }
+}
+
+impl StreamParser
+{
+    pub fn new() -> StreamParser
+    {
+        StreamParser {
+            stack: Vec::new(),
+            in_string: false,
+            escaped: false,
+            capturing_key: false,
+            key: Vec::new(),
+            item: None,
+            skeleton: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Feeds the next chunk of the body.
+    ///
+    /// Fails if a completed item doesn't deserialize.
+    pub fn feed(&mut self, bytes: &[u8]) -> serde_json::Result<()>
+    {
+        for &byte in bytes
+        {
+            if self.item.is_some()
+            {
+                self.feed_item(byte)?;
+            }
+            else
+            {
+                self.feed_skeleton(byte);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the next completed item.
+    pub fn next_item(&mut self) -> Option<Item>
+    {
+        self.ready.pop_front()
+    }
+
+    /// Parses the rest of the response once the body has ended.
+    ///
+    /// Item lists in the returned query are empty. Items that weren't taken
+    /// with [`StreamParser::next_item`] are discarded.
+    ///
+    /// [`StreamParser::next_item`]: struct.StreamParser.html#method.next_item
+    pub fn finish(self) -> serde_json::Result<Query>
+    {
+        serde_json::from_slice(&self.skeleton)
+    }
+
+    fn feed_item(&mut self, byte: u8) -> serde_json::Result<()>
+    {
+        let mut finished = false;
+
+        if let Some(item) = &mut self.item
+        {
+            item.bytes.push(byte);
+
+            if self.in_string
+            {
+                match byte
+                {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => (),
+                }
+            }
+            else
+            {
+                match byte
+                {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => item.depth += 1,
+                    b'}' | b']' => {
+                        item.depth -= 1;
+                        finished = item.depth == 0;
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        if finished
+        {
+            if let Some(item) = self.item.take()
+            {
+                self.ready.push_back(item.kind.parse(&item.bytes)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn feed_skeleton(&mut self, byte: u8)
+    {
+        if self.in_string
+        {
+            self.skeleton.push(byte);
+
+            match byte
+            {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => {
+                    self.in_string = false;
+
+                    if self.capturing_key
+                    {
+                        self.capturing_key = false;
+
+                        if let Some(Frame::Object { key, .. }) = self.stack.last_mut()
+                        {
+                            std::mem::swap(key, &mut self.key);
+                        }
+                    }
+
+                    return;
+                },
+                _ => (),
+            }
+
+            if self.capturing_key
+            {
+                self.key.push(byte);
+            }
+
+            return;
+        }
+
+        let target = self.target();
+
+        match byte
+        {
+            b'{' if target.is_some() => {
+                self.item = Some(PendingItem {
+                    kind: target.unwrap(),
+                    depth: 1,
+                    bytes: vec![byte],
+                });
+
+                return;
+            },
+            b',' | b' ' | b'\t' | b'\r' | b'\n' if target.is_some() => return,
+            b'"' => {
+                self.in_string = true;
+
+                if let Some(Frame::Object { expect_key: true, .. }) = self.stack.last()
+                {
+                    self.capturing_key = true;
+                    self.key.clear();
+                }
+            },
+            b'{' => self.stack.push(Frame::Object { key: Vec::new(), expect_key: true }),
+            b'[' => self.stack.push(Frame::Array),
+            b'}' | b']' => {
+                self.stack.pop();
+            },
+            b':' => {
+                if let Some(Frame::Object { expect_key, .. }) = self.stack.last_mut()
+                {
+                    *expect_key = false;
+                }
+            },
+            b',' => {
+                if let Some(Frame::Object { expect_key, .. }) = self.stack.last_mut()
+                {
+                    *expect_key = true;
+                }
+            },
+            _ => (),
+        }
+
+        self.skeleton.push(byte);
+    }
+
+    // The kind of item held by the array being parsed, if it's `query.<list>`.
+    fn target(&self) -> Option<ItemKind>
+    {
+        match self.stack.as_slice()
+        {
+            [Frame::Object { key: root, .. }, Frame::Object { key: list, .. }, Frame::Array]
+                if root.as_slice() == b"query" => ItemKind::from_key(list),
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the items of a response read from an [`io::Read`].
+///
+/// [`io::Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+pub struct Items<R>
+{
+    reader: R,
+    parser: StreamParser,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+/// Streams the items of the response in `reader`.
+pub fn from_reader<R: Read>(reader: R) -> Items<R>
+{
+    Items {
+        reader,
+        parser: StreamParser::new(),
+        buffer: vec![0; 8 * 1024],
+        eof: false,
+    }
+}
+
+impl<R: Read> Items<R>
+{
+    fn fill(&mut self) -> io::Result<()>
+    {
+        let read = self.reader.read(&mut self.buffer)?;
+
+        if read == 0
+        {
+            self.eof = true;
+        }
+
+        self.parser.feed(&self.buffer[..read])?;
+
+        Ok(())
+    }
+
+    /// Reads the rest of the body and parses everything but the items.
+    ///
+    /// Items that weren't iterated over are discarded.
+    pub fn finish(mut self) -> io::Result<Query>
+    {
+        while !self.eof
+        {
+            self.fill()?;
+
+            while self.parser.next_item().is_some() {}
+        }
+
+        Ok(self.parser.finish()?)
+    }
+}
+
+impl<R: Read> Iterator for Items<R>
+{
+    type Item = io::Result<Item>;
+
+    fn next(&mut self) -> Option<io::Result<Item>>
+    {
+        loop
+        {
+            if let Some(item) = self.parser.next_item()
+            {
+                return Some(Ok(item));
+            }
+
+            if self.eof
+            {
+                return None;
+            }
+
+            if let Err(err) = self.fill()
+            {
+                self.eof = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod stream_tests
+{
+    use super::*;
+
+    const BODY: &str = "{\"batchcomplete\":true,\"continue\":{\"excontinue\":\"2\",\"continue\":\"||\"},\"warnings\":{\"extracts\":{\"warnings\":\"\\\"exlimit\\\" was too large.\"}},\"query\":{\"normalized\":[{\"fromencoded\":false,\"from\":\"death\",\"to\":\"Death\"}],\"pages\": [ {\"ns\":0,\"title\":\"Death\",\"pageid\":8221,\"extract\":\"Death {is} [the] \\\"end\\\"\"} , {\"ns\":0,\"title\":\"Life\",\"pageid\":8222,\"protection\":[{\"type\":\"edit\",\"level\":\"sysop\",\"expiry\":\"infinity\"}]},{\"ns\":0,\"title\":\"Birth\",\"missing\":true}]}}";
+
+    #[test]
+    fn yields_items_across_chunks()
+    {
+        let mut parser = StreamParser::new();
+        let mut titles = vec![];
+
+        for chunk in BODY.as_bytes().chunks(7)
+        {
+            parser.feed(chunk).unwrap();
+
+            while let Some(item) = parser.next_item()
+            {
+                titles.push(item.into_page().unwrap().title);
+            }
+        }
+
+        let tail = parser.finish().unwrap();
+
+        assert_eq!(titles, vec!["Death", "Life", "Birth"]);
+        assert_eq!(tail.query.pages.unwrap().len(), 0);
+        assert_eq!(tail.query.normalized.unwrap()[0].to, "Death");
+        assert_eq!(tail.continue_block.unwrap().ex_continue().unwrap(), "2");
+        assert!(tail.warnings.unwrap().extracts.is_some());
+    }
+
+    #[test]
+    fn reads_items_from_reader()
+    {
+        let mut items = from_reader(BODY.as_bytes());
+
+        let first = items.next().unwrap().unwrap().into_page().unwrap();
+        let tail = items.finish().unwrap();
+
+        assert_eq!(first.extract.unwrap(), "Death {is} [the] \"end\"");
+        assert!(tail.batch_complete);
+    }
+
+    #[test]
+    fn truncated_body_fails()
+    {
+        let mut items = from_reader(&BODY.as_bytes()[..BODY.len() - 2]);
+
+        assert_eq!(items.by_ref().count(), 3);
+        assert!(items.finish().is_err());
+    }
+}
@@ -0,0 +1,356 @@
+//! Decides which failed requests to retry and how long to wait first.
+//!
+//! A [`RetryPolicy`] retries transient failures:
+//! - `429`, `502`, `503` and `504` statuses
+//! - connections that were reset, aborted or timed out
+//! - the `ratelimited`, `internal_api_error_*` and `maxlag` api errors
+//!
+//! Other errors, like `badvalue`, fail right away. The wait grows
+//! exponentially with each retry, with jitter so clients don't retry in step,
+//! and a `Retry-After` header is always honoured. The total time waited for a
+//! request is capped by the budget.
+//!
+//! # Examples
+//! ```
+//! use std::time::Duration;
+//! use wikiquery::client::Client;
+//! use wikiquery::retry::RetryPolicy;
+//!
+//! let mut policy = RetryPolicy::new();
+//!
+//! policy.max_retries(5)
+//!     .budget(Duration::from_secs(60))
+//!     .on_retry(|retry| eprintln!("retry {} in {:?}: {}", retry.attempt, retry.delay, retry.error));
+//!
+//! # let transport = |_request| Ok::<_, wikiquery::error::Error>(http::Response::new(Vec::new()));
+//! let mut client = Client::new(transport);
+//!
+//! client.retry(policy);
+//! ```
+//!
+//! [`RetryPolicy`]: struct.RetryPolicy.html
+
+use http::StatusCode;
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// How long to wait after a `maxlag` error without a `Retry-After` header.
+pub const DEFAULT_LAG_WAIT: Duration = Duration::from_secs(5);
+
+/// A retry about to happen, reported to the hooks of a [`RetryPolicy`].
+///
+/// [`RetryPolicy`]: struct.RetryPolicy.html
+#[derive(Debug)]
+pub struct Retry<'e>
+{
+    /// The number of the retry, starting at 1.
+    pub attempt: u32,
+    /// How long the client waits before retrying.
+    pub delay: Duration,
+    /// The error that is retried.
+    pub error: &'e Error,
+}
+
+type Hook = Arc<dyn Fn(&Retry) + Send + Sync>;
+
+/// When and how long to wait before retrying a failed request.
+#[derive(Clone)]
+pub struct RetryPolicy
+{
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: Duration,
+    hooks: Vec<Hook>,
+}
+
+impl Default for RetryPolicy
+{
+    fn default() -> RetryPolicy
+    {
+        RetryPolicy::new()
+    }
+}
+
+impl fmt::Debug for RetryPolicy
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("RetryPolicy")
+            .field("max_retries", &self.max_retries)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("budget", &self.budget)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl RetryPolicy
+{
+    /// Retries up to 3 times, starting at half a second and waiting at most
+    /// 2 minutes in total.
+    pub fn new() -> RetryPolicy
+    {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            budget: Duration::from_secs(120),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Never retries.
+    pub fn none() -> RetryPolicy
+    {
+        let mut policy = RetryPolicy::new();
+
+        policy.max_retries(0);
+        policy
+    }
+
+    pub fn max_retries(&mut self, retries: u32) -> &mut Self
+    {
+        self.max_retries = retries;
+        self
+    }
+
+    /// The wait before the first retry, doubled for each retry after it.
+    pub fn base_delay(&mut self, delay: Duration) -> &mut Self
+    {
+        self.base_delay = delay;
+        self
+    }
+
+    /// The longest wait between retries, unless `Retry-After` asks for more.
+    pub fn max_delay(&mut self, delay: Duration) -> &mut Self
+    {
+        self.max_delay = delay;
+        self
+    }
+
+    /// The longest total wait for a single request.
+    pub fn budget(&mut self, budget: Duration) -> &mut Self
+    {
+        self.budget = budget;
+        self
+    }
+
+    /// Calls `hook` before each retry.
+    pub fn on_retry<F>(&mut self, hook: F) -> &mut Self
+        where F: Fn(&Retry) + Send + Sync + 'static
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Whether the error is transient and worth retrying.
+    pub fn is_retryable(error: &Error) -> bool
+    {
+        match error
+        {
+            Error::Status(status) => matches!(
+                *status,
+                StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Error::Transport(err) => is_connection_error(err.as_ref()),
+            Error::Api(err) => {
+                let code = &err.error.code;
+
+                code == "ratelimited" || code.starts_with("internal_api_error_")
+            },
+            Error::MaxLag(_) => true,
+            Error::Http(_) | Error::Json(_) => false,
+        }
+    }
+
+    /// How long to wait before retrying after `error`, or `None` to give up.
+    ///
+    /// `attempt` is the number of retries made so far and `waited` the time
+    /// already spent waiting on them. The hooks are called when a retry is due.
+    pub fn next_delay(&self, error: &Error, attempt: u32, retry_after: Option<Duration>, waited: Duration) -> Option<Duration>
+    {
+        if attempt >= self.max_retries || !RetryPolicy::is_retryable(error)
+        {
+            return None;
+        }
+
+        let retry_after = match error
+        {
+            Error::MaxLag(lag) => Some(lag.retry_after.or(retry_after).unwrap_or(DEFAULT_LAG_WAIT)),
+            _ => retry_after,
+        };
+
+        let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+
+        if waited + delay > self.budget
+        {
+            return None;
+        }
+
+        let retry = Retry {
+            attempt: attempt + 1,
+            delay,
+            error,
+        };
+
+        event!(
+            warn,
+            attempt = retry.attempt,
+            delay_ms = delay.as_millis() as u64,
+            error = %error,
+            "retrying request"
+        );
+
+        for hook in &self.hooks
+        {
+            hook(&retry);
+        }
+
+        Some(delay)
+    }
+
+    /// An exponential wait with equal jitter, between half and all of
+    /// `base_delay * 2^attempt`, capped at `max_delay`.
+    fn backoff(&self, attempt: u32) -> Duration
+    {
+        let exponential = self.base_delay
+            .checked_mul(1 << attempt.min(16))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let half = exponential / 2;
+
+        half + half.mul_f64(random_fraction())
+    }
+}
+
+fn is_connection_error(mut err: &(dyn std::error::Error + 'static)) -> bool
+{
+    loop
+    {
+        if let Some(err) = err.downcast_ref::<io::Error>()
+        {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::UnexpectedEof
+            );
+        }
+
+        match err.source()
+        {
+            Some(source) => err = source,
+            None => return false,
+        }
+    }
+}
+
+/// A number in `[0, 1)` from a xorshift generator seeded by the clock.
+fn random_fraction() -> f64
+{
+    static STATE: AtomicU64 = AtomicU64::new(0);
+
+    let mut x = STATE.load(Ordering::Relaxed);
+
+    if x == 0
+    {
+        x = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1;
+    }
+
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+
+    STATE.store(x, Ordering::Relaxed);
+
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod retry_tests
+{
+    use super::*;
+    use crate::error::WikiError;
+    use std::sync::Mutex;
+
+    fn api_error(code: &str) -> Error
+    {
+        let body = format!("{{\"error\":{{\"code\":\"{}\",\"info\":\"Oops.\",\"docref\":\"See api.php.\"}},\"servedby\":\"mw1\"}}", code);
+
+        Error::from(serde_json::from_str::<WikiError>(&body).unwrap())
+    }
+
+    #[test]
+    fn classifies_retryable_errors()
+    {
+        let reset = Error::Transport(Box::new(io::Error::new(io::ErrorKind::ConnectionReset, "reset")));
+        let refused_file = Error::Transport(Box::new(io::Error::new(io::ErrorKind::NotFound, "no fixture")));
+
+        assert!(RetryPolicy::is_retryable(&Error::Status(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(RetryPolicy::is_retryable(&Error::Status(StatusCode::GATEWAY_TIMEOUT)));
+        assert!(!RetryPolicy::is_retryable(&Error::Status(StatusCode::NOT_FOUND)));
+        assert!(RetryPolicy::is_retryable(&reset));
+        assert!(!RetryPolicy::is_retryable(&refused_file));
+        assert!(RetryPolicy::is_retryable(&api_error("ratelimited")));
+        assert!(RetryPolicy::is_retryable(&api_error("internal_api_error_DBQueryError")));
+        assert!(RetryPolicy::is_retryable(&api_error("maxlag")));
+        assert!(!RetryPolicy::is_retryable(&api_error("badvalue")));
+    }
+
+    #[test]
+    fn backs_off_exponentially_with_jitter()
+    {
+        let mut policy = RetryPolicy::new();
+
+        policy.max_retries(10)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(1000))
+            .budget(Duration::from_secs(60));
+
+        let error = Error::Status(StatusCode::SERVICE_UNAVAILABLE);
+
+        for (attempt, full) in [100, 200, 400, 800, 1000, 1000].iter().enumerate()
+        {
+            let delay = policy.next_delay(&error, attempt as u32, None, Duration::from_secs(0)).unwrap();
+            let full = Duration::from_millis(*full);
+
+            assert!(delay >= full / 2 && delay <= full, "{:?} outside of {:?}", delay, full);
+        }
+    }
+
+    #[test]
+    fn honours_retry_after_and_the_budget()
+    {
+        let retries = Arc::new(Mutex::new(Vec::new()));
+        let recorded = retries.clone();
+        let mut policy = RetryPolicy::new();
+
+        policy.budget(Duration::from_secs(10))
+            .on_retry(move |retry| recorded.lock().unwrap().push((retry.attempt, retry.delay)));
+
+        let error = Error::Status(StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = Some(Duration::from_secs(7));
+
+        assert_eq!(policy.next_delay(&error, 0, retry_after, Duration::from_secs(0)), Some(Duration::from_secs(7)));
+        assert_eq!(policy.next_delay(&error, 1, retry_after, Duration::from_secs(7)), None);
+        assert_eq!(policy.next_delay(&error, 3, None, Duration::from_secs(0)), None);
+        assert_eq!(policy.next_delay(&api_error("badvalue"), 0, None, Duration::from_secs(0)), None);
+        assert_eq!(policy.next_delay(&api_error("maxlag"), 0, None, Duration::from_secs(0)), Some(DEFAULT_LAG_WAIT));
+
+        assert_eq!(*retries.lock().unwrap(), vec![(1, Duration::from_secs(7)), (1, DEFAULT_LAG_WAIT)]);
+    }
+}
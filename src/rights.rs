@@ -0,0 +1,259 @@
+//! Detects the limits of the account queries are sent as.
+//!
+//! Accounts with the `apihighlimits` right, such as bots and sysops, may ask
+//! for [`MAX_LIMIT_HIGH`] results per request rather than [`MAX_LIMIT`], and
+//! send [`MAX_TITLES_HIGH_LIMITS`] titles rather than [`MAX_TITLES`].
+//! [`Rights`] are read from the response of [`Rights::query`].
+//!
+//! A [`Client`] fetches them once, the first time a request asks for
+//! [`Limit::Max`], and sends the account's limit in place of `max`.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::{Limit, Query};
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("War").category("Conflicts"))
+//!     .rights(vec!["read", "apihighlimits"]);
+//!
+//! let client = Client::new(wiki);
+//! let mut query = Query::new();
+//!
+//! query.category_members()
+//!     .cm_title("Category:Conflicts")
+//!     .cm_limit(Limit::Max);
+//!
+//! client.execute(&mut query).unwrap();
+//!
+//! let rights = client.rights().unwrap();
+//!
+//! assert!(rights.high_limits());
+//! assert_eq!(rights.max_limit("cmlimit"), Some(5000));
+//! # }
+//! ```
+//!
+//! [`MAX_LIMIT_HIGH`]: constant.MAX_LIMIT_HIGH.html
+//! [`MAX_LIMIT`]: constant.MAX_LIMIT.html
+//! [`MAX_TITLES_HIGH_LIMITS`]: ../batch/constant.MAX_TITLES_HIGH_LIMITS.html
+//! [`MAX_TITLES`]: ../status/constant.MAX_TITLES.html
+//! [`Rights`]: struct.Rights.html
+//! [`Rights::query`]: struct.Rights.html#method.query
+//! [`Client`]: ../client/struct.Client.html
+//! [`Limit::Max`]: ../requests/enum.Limit.html#variant.Max
+
+use http::Request;
+
+use crate::batch::{MAX_EXTRACTS, MAX_TITLES_HIGH_LIMITS};
+use crate::requests::Query;
+use crate::responses;
+use crate::status::MAX_TITLES;
+
+/// The most results a list module returns per request without
+/// `apihighlimits`.
+pub const MAX_LIMIT: usize = 500;
+
+/// The most results a list module returns per request with `apihighlimits`.
+pub const MAX_LIMIT_HIGH: usize = 5000;
+
+/// The right that raises the limits.
+pub const HIGH_LIMITS: &str = "apihighlimits";
+
+/// The rights of an account.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rights
+{
+    rights: Vec<String>,
+}
+
+impl Rights
+{
+    pub fn new<I, S>(rights: I) -> Rights
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        Rights {
+            rights: rights.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// A `meta=userinfo&uiprop=rights` query.
+    pub fn query() -> Query<'static>
+    {
+        let mut query = Query::new();
+
+        query.user_info()
+            .ui_prop("rights");
+
+        query
+    }
+
+    /// Reads the rights from the response of [`Rights::query`].
+    ///
+    /// A response without `userinfo` gives no rights.
+    ///
+    /// [`Rights::query`]: struct.Rights.html#method.query
+    pub fn from_response(response: &responses::Query) -> Rights
+    {
+        let rights = response.query.user_info.as_ref()
+            .and_then(|user_info| user_info.rights.clone())
+            .unwrap_or_default();
+
+        Rights::new(rights)
+    }
+
+    pub fn rights(&self) -> &[String]
+    {
+        &self.rights
+    }
+
+    pub fn has(&self, right: &str) -> bool
+    {
+        self.rights.iter().any(|r| r == right)
+    }
+
+    /// Whether the account has `apihighlimits`.
+    pub fn high_limits(&self) -> bool
+    {
+        self.has(HIGH_LIMITS)
+    }
+
+    /// The most the account may send for a param.
+    ///
+    /// Known for the `aclimit`, `cmlimit` and `exlimit` limits and for
+    /// `titles` and `pageids`.
+    pub fn max_limit(&self, param: &str) -> Option<usize>
+    {
+        let high = self.high_limits();
+
+        match param
+        {
+            "aclimit" | "cmlimit" => Some(if high { MAX_LIMIT_HIGH } else { MAX_LIMIT }),
+            "exlimit" => Some(MAX_EXTRACTS),
+            "titles" | "pageids" => Some(if high { MAX_TITLES_HIGH_LIMITS } else { MAX_TITLES }),
+            _ => None,
+        }
+    }
+
+    /// Replaces every known limit of `max` in the request with the most the
+    /// account may ask for.
+    pub fn resolve<B>(&self, request: &mut Request<B>) -> Result<(), http::Error>
+    {
+        let uri = request.uri().to_string();
+        let (base, query) = match uri.find('?')
+        {
+            Some(i) => (&uri[..i], &uri[i + 1..]),
+            None => return Ok(()),
+        };
+        let params: Vec<String> = query.split('&')
+            .map(|param| match max_limit_param(param).and_then(|name| Some((name, self.max_limit(name)?)))
+            {
+                Some((name, max)) => format!("{}={}", name, max),
+                None => param.to_string(),
+            })
+            .collect();
+
+        *request.uri_mut() = format!("{}?{}", base, params.join("&"))
+            .parse()
+            .map_err(http::Error::from)?;
+
+        Ok(())
+    }
+}
+
+/// A request for [`Rights::query`] to the same wiki as `like`.
+///
+/// [`Rights::query`]: struct.Rights.html#method.query
+pub(crate) fn request_like<B>(like: &Request<B>) -> Result<Request<()>, http::Error>
+{
+    let mut request = Rights::query().build()?;
+    let uri = like.uri();
+
+    if let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority_part())
+    {
+        let path = request.uri().path_and_query().map_or("/", |path| path.as_str());
+
+        *request.uri_mut() = format!("{}://{}{}", scheme, authority, path)
+            .parse()
+            .map_err(http::Error::from)?;
+    }
+
+    Ok(request)
+}
+
+/// Whether the request asks for `max` of a known limit.
+pub(crate) fn asks_for_max<B>(request: &Request<B>) -> bool
+{
+    request.uri().query()
+        .unwrap_or("")
+        .split('&')
+        .filter_map(max_limit_param)
+        .any(|name| Rights::default().max_limit(name).is_some())
+}
+
+/// The name of a `*limit=max` param.
+fn max_limit_param(param: &str) -> Option<&str>
+{
+    let mut pair = param.splitn(2, '=');
+    let name = pair.next()?;
+
+    Some(name).filter(|name| name.ends_with("limit") && pair.next() == Some("max"))
+}
+
+#[cfg(test)]
+mod rights_tests
+{
+    use super::*;
+    use crate::requests::Limit;
+
+    #[test]
+    fn resolves_max_limits()
+    {
+        let mut query = Query::new();
+
+        query.category_members()
+            .cm_title("Category:Death")
+            .cm_limit(Limit::Max);
+        query.pages()
+            .extracts()
+            .ex_limit(Limit::Max);
+        query.all_categories()
+            .ac_limit(Limit::Value(20));
+
+        let request = query.build().unwrap();
+
+        assert!(asks_for_max(&request));
+
+        let local = Request::get("http://127.0.0.1:8080/w/api.php?list=categorymembers&cmlimit=max").body(()).unwrap();
+        let rights = request_like(&local).unwrap();
+
+        assert_eq!(rights.uri().authority_part().unwrap(), "127.0.0.1:8080");
+        assert!(rights.uri().query().unwrap().contains("meta=userinfo"));
+
+        let user = Rights::new(vec!["read"]);
+        let bot = Rights::new(vec!["read", "bot", "apihighlimits"]);
+
+        for (rights, expected) in [(user, "cmlimit=500"), (bot, "cmlimit=5000")]
+        {
+            let mut request = query.build().unwrap();
+
+            rights.resolve(&mut request).unwrap();
+
+            let params: Vec<&str> = request.uri().query().unwrap().split('&').collect();
+
+            assert!(params.contains(&expected));
+            assert!(params.contains(&"exlimit=20"));
+            assert!(params.contains(&"aclimit=20"));
+            assert!(!asks_for_max(&request));
+        }
+
+        let response: responses::Query = serde_json::from_str("{\"batchcomplete\":true,\"query\":{\"userinfo\":{\"id\":1,\"name\":\"Bot\",\"rights\":[\"read\",\"apihighlimits\"]}}}").unwrap();
+
+        assert!(Rights::from_response(&response).high_limits());
+        assert_eq!(Rights::from_response(&response).max_limit("titles"), Some(MAX_TITLES_HIGH_LIMITS));
+    }
+}
@@ -0,0 +1,167 @@
+//! Checks the existence and validity of many pages at once.
+//! 
+//! The api only accepts a limited number of titles per request, so a
+//! [`StatusCheck`] splits its titles into chunks and builds one [`Query`] per
+//! chunk. Send each query, then hand all of the responses back to
+//! [`StatusCheck::statuses`] to get a [`PageStatus`] for every title.
+//! 
+//! [`StatusCheck`]: struct.StatusCheck.html
+//! [`StatusCheck::statuses`]: struct.StatusCheck.html#method.statuses
+//! [`Query`]: ../requests/struct.Query.html
+//! [`PageStatus`]: ../responses/pages/enum.PageStatus.html
+
+use std::collections::HashMap;
+
+use crate::requests::{self, Query};
+use crate::responses;
+use crate::responses::pages::PageStatus;
+
+/// The maximum number of titles the api accepts in one request without `apihighlimits`.
+pub const MAX_TITLES: usize = 50;
+
+/// A batch of titles to check.
+/// 
+/// # Examples
+/// ```
+/// use wikiquery::status::StatusCheck;
+/// 
+/// let check = StatusCheck::new(vec!["Death", "Main page", "Special:Random"]);
+/// 
+/// for mut query in check.queries()
+/// {
+///     let request = query.build().unwrap();
+///     /*
+///         Send the request and collect the responses::Query values
+///     */
+/// }
+/// 
+/// # let responses = vec![];
+/// let statuses = check.statuses(&responses);
+/// ```
+pub struct StatusCheck
+{
+    titles: Vec<String>,
+    chunk_size: usize,
+}
+
+impl StatusCheck
+{
+    pub fn new<I, S>(titles: I) -> StatusCheck
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        StatusCheck {
+            titles: titles.into_iter().map(Into::into).collect(),
+            chunk_size: MAX_TITLES,
+        }
+    }
+
+    /// Sets the number of titles sent per request.
+    /// 
+    /// Defaults to [`MAX_TITLES`]. Accounts with `apihighlimits` may use up to 500.
+    /// 
+    /// [`MAX_TITLES`]: constant.MAX_TITLES.html
+    pub fn chunk_size(&mut self, chunk_size: usize) -> &mut Self
+    {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn titles(&self) -> &[String]
+    {
+        &self.titles
+    }
+
+    /// Builds one query per chunk of titles.
+    /// 
+    /// The info prop is requested so redirects can be detected.
+    pub fn queries(&self) -> Vec<Query<'static>>
+    {
+        self.titles
+            .chunks(self.chunk_size)
+            .map(|chunk| {
+                let titles = chunk.iter()
+                    .map(|title| requests::encode_value(title))
+                    .collect::<Vec<_>>()
+                    .join("|");
+
+                let mut query = Query::new();
+
+                query.pages()
+                    .titles(titles)
+                    .info();
+
+                query
+            })
+            .collect()
+    }
+
+    /// Matches the pages in `responses` to the titles of the check.
+    /// 
+    /// Titles are followed through the `normalized` block of each response.
+    /// A title whose page isn't found in any response is given `None`.
+    pub fn statuses(&self, responses: &[responses::Query]) -> Vec<(String, Option<PageStatus>)>
+    {
+        let mut normalized = HashMap::new();
+        let mut statuses = HashMap::new();
+
+        for response in responses
+        {
+            let block = &response.query;
+
+            for n in block.normalized.iter().flatten()
+            {
+                normalized.insert(n.from.as_str(), n.to.as_str());
+            }
+
+            for page in block.pages.iter().flatten()
+            {
+                statuses.insert(page.title.as_str(), page.status());
+            }
+        }
+
+        self.titles.iter()
+            .map(|title| {
+                let resolved = normalized.get(title.as_str())
+                    .copied()
+                    .unwrap_or(title.as_str());
+
+                (title.clone(), statuses.get(resolved).cloned())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod status_tests
+{
+    use super::*;
+    use crate::test::helpers::*;
+
+    #[test]
+    fn chunks_titles()
+    {
+        let titles: Vec<String> = (0..120).map(|i| format!("Page {}", i)).collect();
+        let check = StatusCheck::new(titles);
+        let mut queries = check.queries();
+
+        assert_eq!(queries.len(), 3);
+        assert_query_contains(&mut queries[0], &["titles=Page%200|Page%201|", "prop=info"]);
+        assert_query_contains(&mut queries[2], &["titles=Page%20100|"]);
+    }
+
+    #[test]
+    fn maps_statuses_through_normalized_titles()
+    {
+        let resp = "{\"batchcomplete\":true,\"query\":{\"normalized\":[{\"fromencoded\":false,\"from\":\"death\",\"to\":\"Death\"}],\"pages\":[{\"ns\":0,\"title\":\"Death\",\"pageid\":8221},{\"ns\":0,\"title\":\"Nonexistent page xyz\",\"missing\":true}]}}";
+        let response: responses::Query = serde_json::from_str(resp).unwrap();
+
+        let check = StatusCheck::new(vec!["death", "Nonexistent page xyz", "Unsent"]);
+
+        assert_eq!(check.statuses(&[response]), vec![
+            ("death".to_string(), Some(PageStatus::Exists)),
+            ("Nonexistent page xyz".to_string(), Some(PageStatus::Missing)),
+            ("Unsent".to_string(), None),
+        ]);
+    }
+}
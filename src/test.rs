@@ -16,4 +16,16 @@ pub mod helpers
             assert!(query_str.contains(c));
         }
     }
+
+    pub fn assert_query_excludes(query: &mut Query, excludes: &[&'static str])
+    {
+        let request = query.build().unwrap();
+        let (parts, _body) = request.into_parts();
+        let query_str = parts.uri.query().unwrap();
+
+        for e in excludes
+        {
+            assert!(!query_str.contains(e));
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+//! Instrumentation with `tracing`, compiled out without the `tracing` feature.
+//!
+//! The macros take the same arguments as their `tracing` counterparts. Without
+//! the feature their arguments aren't evaluated, so they should only read
+//! values that are used anyway.
+
+use http::Request;
+
+/// Emits an event at the given level, for example `event!(debug, status, "response")`.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+
+/// Enters a debug span until the end of the enclosing block.
+macro_rules! enter_span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)+).entered();
+    };
+}
+
+/// Runs a future in a debug span, since an entered span must not be held
+/// across an `.await`. The span is made before the future is evaluated, so it
+/// can borrow what the future takes.
+#[cfg_attr(not(feature = "async"), allow(unused_macros))]
+macro_rules! instrument {
+    ($future:expr, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!($($arg)+);
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument($future, span);
+        #[cfg(not(feature = "tracing"))]
+        let future = $future;
+
+        future
+    }};
+}
+
+/// The `list`, `prop`, `meta` and `generator` modules of a request, joined
+/// with `|`.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) fn modules<B>(request: &Request<B>) -> String
+{
+    request.uri().query()
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|param| {
+            let mut pair = param.splitn(2, '=');
+            let name = pair.next()?;
+
+            Some(pair.next().unwrap_or("")).filter(|_| ["list", "prop", "meta", "generator"].contains(&name))
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// The number of params in the query string of a request.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) fn param_count<B>(request: &Request<B>) -> usize
+{
+    request.uri().query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .count()
+}
+
+#[cfg(test)]
+mod trace_tests
+{
+    use super::*;
+
+    #[test]
+    fn describes_requests()
+    {
+        let request = Request::get("https://en.wikipedia.org/w/api.php?&action=query&prop=info|extracts&list=categorymembers&titles=Death")
+            .body(())
+            .unwrap();
+
+        assert_eq!(modules(&request), "info|extracts|categorymembers");
+        assert_eq!(param_count(&request), 4);
+    }
+}
@@ -0,0 +1,108 @@
+//! Sends requests built by the crate.
+//!
+//! A [`Transport`] takes an [`http`] [`Request`] and returns the status,
+//! headers and body of its response. The [`Client`] runs queries with any
+//! transport, so the http stack can be swapped or faked in tests.
+//!
+//! A blocking transport is available with the `blocking` feature, see
+//! [`blocking::Blocking`]. The `async` feature adds [`AsyncTransport`] for
+//! the [`AsyncClient`] and a pooled transport, see [`asynchronous::Async`].
+//! Both ask for gzip with `Accept-Encoding`, and their http clients
+//! decompress responses before returning them.
+//!
+//! Transports can be wrapped to cache responses, see [`cache::Cached`], to
+//! limit their rate, see [`throttle::Throttled`], or to record and replay them
+//! in tests, see [`cassette::Cassette`].
+//!
+//! [`Transport`]: trait.Transport.html
+//! [`Client`]: ../client/struct.Client.html
+//! [`http`]: https://docs.rs/http
+//! [`Request`]: https://docs.rs/http/0.1/http/request/struct.Request.html
+//! [`blocking::Blocking`]: blocking/struct.Blocking.html
+//! [`AsyncTransport`]: trait.AsyncTransport.html
+//! [`AsyncClient`]: ../client/asynchronous/struct.AsyncClient.html
+//! [`asynchronous::Async`]: asynchronous/struct.Async.html
+//! [`cache::Cached`]: cache/struct.Cached.html
+//! [`throttle::Throttled`]: throttle/struct.Throttled.html
+//! [`cassette::Cassette`]: cassette/struct.Cassette.html
+
+use http::{Request, Response};
+
+#[cfg(feature = "async")]
+use futures::future::{BoxFuture, Future};
+
+use crate::error::Error;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cache;
+pub mod cassette;
+pub mod throttle;
+
+/// Sends a request and reads the whole response.
+///
+/// Unsuccessful statuses should be returned as responses, not errors. Only
+/// failures to send the request or read the response are errors, usually
+/// [`Error::Transport`].
+///
+/// Closures taking a request and returning a response are transports.
+///
+/// # Examples
+/// ```
+/// use http::Response;
+/// use wikiquery::client::Client;
+/// use wikiquery::requests::Query;
+///
+/// let transport = |_request| {
+///     Ok(Response::new(br#"{"batchcomplete":true,"query":{}}"#.to_vec()))
+/// };
+///
+/// let client = Client::new(transport);
+/// let mut query = Query::new();
+///
+/// query.all_categories();
+///
+/// let response = client.execute(&mut query).unwrap();
+///
+/// assert!(response.batch_complete);
+/// ```
+///
+/// [`Error::Transport`]: ../error/enum.Error.html#variant.Transport
+pub trait Transport
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>;
+}
+
+impl<F> Transport for F
+    where F: Fn(Request<()>) -> Result<Response<Vec<u8>>, Error>
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        self(request)
+    }
+}
+
+/// Sends a request without blocking and reads the whole response.
+///
+/// The same rules as [`Transport`] apply. Closures returning a future of a
+/// response are async transports.
+///
+/// [`Transport`]: trait.Transport.html
+#[cfg(feature = "async")]
+pub trait AsyncTransport
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>;
+}
+
+#[cfg(feature = "async")]
+impl<F, Fut> AsyncTransport for F
+    where F: Fn(Request<()>) -> Fut,
+          Fut: Future<Output = Result<Response<Vec<u8>>, Error>> + Send + 'static
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(self(request))
+    }
+}
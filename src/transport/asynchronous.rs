@@ -0,0 +1,114 @@
+//! An async transport built on [`reqwest`].
+//!
+//! Connections are pooled by the underlying [`reqwest::Client`], so one
+//! transport should be shared by every query sent to the same wiki. It must be
+//! used from within a [`tokio`] runtime.
+//!
+//! # Examples
+//! ```no_run
+//! use futures::StreamExt;
+//! use wikiquery::client::asynchronous::AsyncClient;
+//! use wikiquery::requests::Query;
+//! use wikiquery::transport::asynchronous::Async;
+//!
+//! # async fn run()
+//! # {
+//! let client = AsyncClient::new(Async::new());
+//! let mut query = Query::new();
+//!
+//! query.category_members()
+//!     .cm_title("Category:War")
+//!     .cm_limit("500");
+//!
+//! let mut members = Box::pin(client.category_members(query, Some(1000)));
+//!
+//! while let Some(member) = members.next().await
+//! {
+//!     println!("{:?}", member.unwrap().title);
+//! }
+//! # }
+//! ```
+//!
+//! [`reqwest`]: https://docs.rs/reqwest
+//! [`reqwest::Client`]: https://docs.rs/reqwest/0.11/reqwest/struct.Client.html
+//! [`tokio`]: https://docs.rs/tokio
+
+use futures::future::BoxFuture;
+use http::{Request, Response};
+
+use crate::error::Error;
+use super::AsyncTransport;
+
+/// Sends requests without blocking over a pool of connections.
+///
+/// Responses are asked for as gzip and decompressed by [`reqwest`].
+///
+/// [`reqwest`]: https://docs.rs/reqwest
+#[derive(Debug, Clone)]
+pub struct Async
+{
+    client: reqwest::Client,
+}
+
+impl Async
+{
+    pub fn new() -> Async
+    {
+        Async::with_client(reqwest::Client::new())
+    }
+
+    /// Sends requests with a configured client, for example one with timeouts
+    /// or a larger pool.
+    pub fn with_client(client: reqwest::Client) -> Async
+    {
+        Async {
+            client,
+        }
+    }
+}
+
+impl Default for Async
+{
+    fn default() -> Async
+    {
+        Async::new()
+    }
+}
+
+fn transport_error<E>(err: E) -> Error
+    where E: std::error::Error + Send + Sync + 'static
+{
+    Error::Transport(Box::new(err))
+}
+
+impl AsyncTransport for Async
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(async move {
+            let (parts, _body) = request.into_parts();
+            let method = reqwest::Method::from_bytes(parts.method.as_str().as_bytes())
+                .map_err(transport_error)?;
+            let mut req = self.client.request(method, parts.uri.to_string());
+
+            for (name, value) in parts.headers.iter()
+            {
+                req = req.header(name.as_str(), value.as_bytes());
+            }
+
+            let resp = req.send().await.map_err(transport_error)?;
+            let mut builder = Response::builder();
+
+            builder.status(resp.status().as_u16());
+
+            for (name, value) in resp.headers()
+            {
+                builder.header(name.as_str(), value.as_bytes());
+            }
+
+            let body = resp.bytes().await.map_err(transport_error)?;
+
+            Ok(builder.body(body.to_vec())?)
+        })
+    }
+}
@@ -0,0 +1,164 @@
+//! A blocking transport built on [`ureq`].
+//!
+//! # Examples
+//! ```no_run
+//! use wikiquery::client::Client;
+//! use wikiquery::requests::Query;
+//! use wikiquery::transport::blocking::Blocking;
+//!
+//! let client = Client::new(Blocking::new());
+//! let mut query = Query::new();
+//!
+//! query.category_members()
+//!     .cm_title("Category:War")
+//!     .cm_limit("500");
+//!
+//! for response in client.paginate(query)
+//! {
+//!     let response = response.unwrap();
+//!
+//!     for member in response.query.category_members.unwrap_or_default()
+//!     {
+//!         println!("{:?}", member.title);
+//!     }
+//! }
+//! ```
+//!
+//! [`ureq`]: https://docs.rs/ureq
+
+use http::{Request, Response};
+
+use std::io::Read;
+
+use crate::error::Error;
+use super::Transport;
+
+/// Sends requests on the current thread, reusing connections between them.
+///
+/// Responses are asked for as gzip and decompressed by [`ureq`].
+///
+/// [`ureq`]: https://docs.rs/ureq
+#[derive(Debug, Clone)]
+pub struct Blocking
+{
+    agent: ureq::Agent,
+}
+
+impl Blocking
+{
+    pub fn new() -> Blocking
+    {
+        Blocking::with_agent(ureq::Agent::new())
+    }
+
+    /// Sends requests with a configured agent, for example one with timeouts.
+    pub fn with_agent(agent: ureq::Agent) -> Blocking
+    {
+        Blocking {
+            agent,
+        }
+    }
+}
+
+impl Default for Blocking
+{
+    fn default() -> Blocking
+    {
+        Blocking::new()
+    }
+}
+
+impl Transport for Blocking
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        let (parts, _body) = request.into_parts();
+        let mut req = self.agent.request(parts.method.as_str(), &parts.uri.to_string());
+
+        for (name, value) in parts.headers.iter()
+        {
+            let value = value.to_str()
+                .map_err(|err| Error::Transport(Box::new(err)))?;
+
+            req = req.set(name.as_str(), value);
+        }
+
+        let resp = match req.call()
+        {
+            Ok(resp) => resp,
+            Err(ureq::Error::Status(_, resp)) => resp,
+            Err(err) => return Err(Error::Transport(Box::new(err))),
+        };
+
+        let mut builder = Response::builder();
+
+        builder.status(resp.status());
+
+        for name in resp.headers_names()
+        {
+            for value in resp.all(&name)
+            {
+                builder.header(name.as_str(), value);
+            }
+        }
+
+        let mut body = Vec::new();
+
+        resp.into_reader()
+            .read_to_end(&mut body)
+            .map_err(|err| Error::Transport(Box::new(err)))?;
+
+        Ok(builder.body(body)?)
+    }
+}
+
+#[cfg(test)]
+mod blocking_tests
+{
+    use super::*;
+
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn sends_request_and_reads_response()
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n")
+            {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+
+            let body = "{\"batchcomplete\":true,\"query\":{}}";
+
+            write!(stream, "HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        let request = Request::builder()
+            .uri(format!("http://{}/w/api.php?action=query", addr))
+            .header("Api-User-Agent", "wikiquery-tests")
+            .body(())
+            .unwrap();
+
+        let response = Blocking::new().send(request).unwrap();
+        let request = server.join().unwrap();
+
+        assert!(request.starts_with("GET /w/api.php?action=query HTTP/1.1"));
+        assert!(request.to_lowercase().contains("api-user-agent: wikiquery-tests"));
+        assert!(request.to_lowercase().contains("accept-encoding: gzip"));
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert_eq!(response.body(), b"{\"batchcomplete\":true,\"query\":{}}");
+    }
+}
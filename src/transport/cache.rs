@@ -0,0 +1,925 @@
+//! Caches responses between the client and another transport.
+//!
+//! [`Cached`] serves a response from its [`Store`] while it's fresh and sends
+//! the request with its inner transport otherwise. Responses are keyed by
+//! [`cache_key`], so the same query always hits the same entry whatever order
+//! its params were added in. Two stores are provided: [`MemoryStore`], which
+//! keeps the most recently used responses, and [`DiskStore`], which keeps
+//! them in a directory across runs.
+//!
+//! How long a response stays fresh depends on the modules of the query, see
+//! [`Cached::module_ttl`]. Queries pinned to revisions with `revids` never
+//! change, so their responses never expire.
+//!
+//! When identical requests are sent at the same time, only the first is sent
+//! and the others wait for its response. How requests were served is counted
+//! in [`CacheStats`].
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use std::time::Duration;
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::Query;
+//! use wikiquery::transport::cache::{Cached, MemoryStore};
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("Death").extract("Death is the end of life."));
+//!
+//! let mut cached = Cached::new(wiki, MemoryStore::new(1000));
+//!
+//! cached.ttl(Duration::from_secs(3600))
+//!     .module_ttl("info", Duration::from_secs(60));
+//!
+//! let client = Client::new(cached);
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles("Death")
+//!     .extracts();
+//!
+//! client.execute(&mut query).unwrap();
+//! client.execute(&mut query).unwrap();
+//!
+//! assert_eq!(client.transport().store().len(), 1);
+//! # }
+//! ```
+//!
+//! [`Cached`]: struct.Cached.html
+//! [`Store`]: trait.Store.html
+//! [`cache_key`]: fn.cache_key.html
+//! [`MemoryStore`]: struct.MemoryStore.html
+//! [`DiskStore`]: struct.DiskStore.html
+//! [`Cached::module_ttl`]: struct.Cached.html#method.module_ttl
+//! [`CacheStats`]: struct.CacheStats.html
+
+use http::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "async")]
+use futures::channel::oneshot;
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+use crate::error::Error;
+use super::cassette::{canonical_key, Interaction};
+use super::Transport;
+#[cfg(feature = "async")]
+use super::AsyncTransport;
+
+/// How long responses stay fresh when none of their modules has a ttl.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The key a response is cached under.
+///
+/// This is the [`canonical_key`] of the request without `maxlag`, which
+/// doesn't change the response.
+///
+/// [`canonical_key`]: ../cassette/fn.canonical_key.html
+pub fn cache_key<B>(request: &Request<B>) -> String
+{
+    let canonical = canonical_key(request);
+    let (path, query) = canonical.split_at(canonical.find('?').map_or(canonical.len(), |at| at + 1));
+    let params: Vec<&str> = query.split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("maxlag="))
+        .collect();
+
+    format!("{}{}", path, params.join("&"))
+}
+
+/// A cached response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry
+{
+    pub response: Interaction,
+    /// When the response goes stale, or `None` if it never does.
+    pub expires: Option<SystemTime>,
+}
+
+impl Entry
+{
+    pub fn is_fresh(&self) -> bool
+    {
+        self.expires.is_none_or(|expires| expires > SystemTime::now())
+    }
+}
+
+/// Where cached responses are kept.
+pub trait Store
+{
+    fn get(&self, key: &str) -> io::Result<Option<Entry>>;
+
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()>;
+
+    fn remove(&self, key: &str) -> io::Result<()>;
+
+    fn clear(&self) -> io::Result<()>;
+}
+
+/// Keeps up to a number of responses in memory, dropping the least recently
+/// used first.
+#[derive(Debug)]
+pub struct MemoryStore
+{
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+#[derive(Debug, Default)]
+struct Lru
+{
+    entries: HashMap<String, (Entry, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru
+{
+    fn touch(&mut self, key: &str) -> Option<&Entry>
+    {
+        self.tick += 1;
+
+        let tick = self.tick;
+        let (entry, used) = self.entries.get_mut(key)?;
+
+        self.order.remove(used);
+        self.order.insert(tick, key.to_string());
+        *used = tick;
+
+        Some(entry)
+    }
+}
+
+impl MemoryStore
+{
+    pub fn new(capacity: usize) -> MemoryStore
+    {
+        MemoryStore {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.lru.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+impl Store for MemoryStore
+{
+    fn get(&self, key: &str) -> io::Result<Option<Entry>>
+    {
+        Ok(self.lru.lock().unwrap().touch(key).cloned())
+    }
+
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()>
+    {
+        let mut lru = self.lru.lock().unwrap();
+
+        lru.tick += 1;
+
+        let tick = lru.tick;
+
+        if let Some((_, used)) = lru.entries.insert(key.to_string(), (entry, tick))
+        {
+            lru.order.remove(&used);
+        }
+
+        lru.order.insert(tick, key.to_string());
+
+        while lru.entries.len() > self.capacity
+        {
+            let oldest = match lru.order.keys().next()
+            {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+
+            if let Some(key) = lru.order.remove(&oldest)
+            {
+                lru.entries.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()>
+    {
+        let mut lru = self.lru.lock().unwrap();
+
+        if let Some((_, used)) = lru.entries.remove(key)
+        {
+            lru.order.remove(&used);
+        }
+
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()>
+    {
+        *self.lru.lock().unwrap() = Lru::default();
+        Ok(())
+    }
+}
+
+/// Keeps responses as json files in a directory, one per key.
+#[derive(Debug, Clone)]
+pub struct DiskStore
+{
+    dir: PathBuf,
+}
+
+impl DiskStore
+{
+    /// Stores responses in `dir`, which is created if it's missing.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<DiskStore>
+    {
+        let dir = dir.into();
+
+        fs::create_dir_all(&dir)?;
+
+        Ok(DiskStore {
+            dir,
+        })
+    }
+
+    pub fn dir(&self) -> &Path
+    {
+        &self.dir
+    }
+
+    /// A file named by the FNV-1a hash of the key, which is stable across
+    /// runs and platforms.
+    fn path(&self, key: &str) -> PathBuf
+    {
+        let hash = key.bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+
+        self.dir.join(format!("{:016x}.json", hash))
+    }
+}
+
+/// Numbers the temporary files of disk stores, so writers of the same key
+/// never share one.
+static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+
+impl Store for DiskStore
+{
+    fn get(&self, key: &str) -> io::Result<Option<Entry>>
+    {
+        let bytes = match fs::read(self.path(key))
+        {
+            Ok(bytes) => bytes,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let (stored_key, entry): (String, Entry) = serde_json::from_slice(&bytes)?;
+
+        Ok(Some(entry).filter(|_| stored_key == key))
+    }
+
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()>
+    {
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("json.{}.{}.tmp", process::id(), NEXT_TMP.fetch_add(1, Ordering::Relaxed)));
+
+        fs::write(&tmp, serde_json::to_vec(&(key, entry))?)?;
+        fs::rename(&tmp, &path)
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()>
+    {
+        match fs::remove_file(self.path(key))
+        {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn clear(&self) -> io::Result<()>
+    {
+        for file in fs::read_dir(&self.dir)?
+        {
+            let path = file?.path();
+
+            if path.extension().is_some_and(|extension| extension == "json")
+            {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How the requests of a [`Cached`] were served.
+///
+/// [`Cached`]: struct.Cached.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats
+{
+    /// Requests served from the store.
+    pub hits: u64,
+    /// Requests sent with the inner transport.
+    pub misses: u64,
+    /// Requests served with the response of an identical request in flight.
+    pub coalesced: u64,
+    /// Responses put in the store.
+    pub stored: u64,
+}
+
+impl CacheStats
+{
+    /// The share of requests that weren't sent, from 0 to 1.
+    pub fn hit_ratio(&self) -> f64
+    {
+        let served = self.hits + self.coalesced;
+        let total = served + self.misses;
+
+        if total == 0 { 0.0 } else { served as f64 / total as f64 }
+    }
+
+    /// The counters in the Prometheus text format, named after `prefix`.
+    pub fn to_prometheus(&self, prefix: &str) -> String
+    {
+        let counters = [
+            ("cache_hits_total", "Requests served from the store.", self.hits),
+            ("cache_misses_total", "Requests sent with the inner transport.", self.misses),
+            ("cache_coalesced_total", "Requests served with the response of an identical request in flight.", self.coalesced),
+            ("cache_stored_total", "Responses put in the store.", self.stored),
+        ];
+        let mut out = String::new();
+
+        for (name, help, value) in counters.iter()
+        {
+            let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} counter", prefix, name);
+            let _ = writeln!(out, "{}_{} {}", prefix, name, value);
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters
+{
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+    stored: AtomicU64,
+}
+
+impl Counters
+{
+    fn count(counter: &AtomicU64)
+    {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Waits for the response of an identical request in flight.
+enum Waiter
+{
+    Thread(mpsc::Sender<Option<Interaction>>),
+    #[cfg(feature = "async")]
+    Task(oneshot::Sender<Option<Interaction>>),
+}
+
+enum Begin<'c, R>
+{
+    Hit(Interaction),
+    Wait(R),
+    Lead(Flight<'c>),
+}
+
+/// A request being sent for every identical request that waits on it.
+///
+/// The waiters get the response once it's finished, or `None` if the request
+/// failed or was dropped, and then send the request themselves.
+struct Flight<'c>
+{
+    inflight: &'c Mutex<HashMap<String, Vec<Waiter>>>,
+    key: String,
+    response: Option<Interaction>,
+}
+
+impl<'c> Flight<'c>
+{
+    fn finish(mut self, response: Option<Interaction>)
+    {
+        self.response = response;
+    }
+}
+
+impl<'c> Drop for Flight<'c>
+{
+    fn drop(&mut self)
+    {
+        let waiters = self.inflight.lock()
+            .map(|mut inflight| inflight.remove(&self.key).unwrap_or_default())
+            .unwrap_or_default();
+
+        for waiter in waiters
+        {
+            // A waiter that gave up has dropped its receiver, which is fine.
+            match waiter
+            {
+                Waiter::Thread(sender) => {
+                    let _ = sender.send(self.response.clone());
+                },
+                #[cfg(feature = "async")]
+                Waiter::Task(sender) => {
+                    let _ = sender.send(self.response.clone());
+                },
+            }
+        }
+    }
+}
+
+/// Serves fresh responses from a store and sends the other requests with
+/// another transport.
+pub struct Cached<T, S>
+{
+    inner: T,
+    store: S,
+    ttl: Duration,
+    module_ttls: HashMap<String, Duration>,
+    inflight: Mutex<HashMap<String, Vec<Waiter>>>,
+    counters: Counters,
+}
+
+impl<T, S: Store> Cached<T, S>
+{
+    pub fn new(inner: T, store: S) -> Cached<T, S>
+    {
+        Cached {
+            inner,
+            store,
+            ttl: DEFAULT_TTL,
+            module_ttls: HashMap::new(),
+            inflight: Mutex::new(HashMap::new()),
+            counters: Counters::default(),
+        }
+    }
+
+    /// How long responses stay fresh, unless one of their modules has its own
+    /// ttl. Defaults to [`DEFAULT_TTL`].
+    ///
+    /// [`DEFAULT_TTL`]: constant.DEFAULT_TTL.html
+    pub fn ttl(&mut self, ttl: Duration) -> &mut Self
+    {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long responses of a `list`, `prop`, `meta` or `generator` module
+    /// stay fresh, for example `categorymembers` or `extracts`.
+    ///
+    /// A response of several modules expires with the shortest ttl among them.
+    pub fn module_ttl<M: Into<String>>(&mut self, module: M, ttl: Duration) -> &mut Self
+    {
+        self.module_ttls.insert(module.into(), ttl);
+        self
+    }
+
+    pub fn inner(&self) -> &T
+    {
+        &self.inner
+    }
+
+    pub fn store(&self) -> &S
+    {
+        &self.store
+    }
+
+    /// How the requests were served so far.
+    pub fn stats(&self) -> CacheStats
+    {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            coalesced: self.counters.coalesced.load(Ordering::Relaxed),
+            stored: self.counters.stored.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drops the cached response of the request, if any.
+    pub fn invalidate<B>(&self, request: &Request<B>) -> io::Result<()>
+    {
+        self.store.remove(&cache_key(request))
+    }
+
+    /// Drops every cached response.
+    pub fn clear(&self) -> io::Result<()>
+    {
+        self.store.clear()
+    }
+
+    /// When the response to the request goes stale.
+    fn expires<B>(&self, request: &Request<B>) -> Option<SystemTime>
+    {
+        let params: Vec<(&str, &str)> = request.uri().query()
+            .unwrap_or("")
+            .split('&')
+            .filter_map(|param| {
+                let mut pair = param.splitn(2, '=');
+
+                Some((pair.next()?, pair.next().unwrap_or("")))
+            })
+            .collect();
+
+        if params.iter().any(|(name, _)| *name == "revids")
+        {
+            return None;
+        }
+
+        let ttl = params.iter()
+            .filter(|(name, _)| ["list", "prop", "meta", "generator"].contains(name))
+            .flat_map(|(_, value)| value.split('|').flat_map(|value| value.split("%7C")))
+            .filter_map(|module| self.module_ttls.get(module))
+            .min()
+            .copied()
+            .unwrap_or(self.ttl);
+
+        Some(SystemTime::now() + ttl)
+    }
+
+    /// Serves a fresh response, joins an identical request in flight, or
+    /// leads a new one. `wait` makes the waiter for the second case.
+    ///
+    /// The store is read before locking, so hits don't wait on each other.
+    /// It's read again under the lock, in case a request in flight finished
+    /// in between.
+    fn begin<R, F>(&self, key: &str, wait: F) -> Begin<'_, R>
+        where F: FnOnce() -> (Waiter, R)
+    {
+        if let Some(response) = self.fresh(key)
+        {
+            return Begin::Hit(response);
+        }
+
+        let mut inflight = self.inflight.lock().unwrap();
+
+        if let Some(response) = self.fresh(key)
+        {
+            return Begin::Hit(response);
+        }
+
+        match inflight.get_mut(key)
+        {
+            Some(waiters) => {
+                let (waiter, receiver) = wait();
+
+                waiters.push(waiter);
+                Begin::Wait(receiver)
+            },
+            None => {
+                Counters::count(&self.counters.misses);
+                inflight.insert(key.to_string(), Vec::new());
+
+                Begin::Lead(Flight {
+                    inflight: &self.inflight,
+                    key: key.to_string(),
+                    response: None,
+                })
+            },
+        }
+    }
+
+    /// The stored response of the key, counted as a hit, if it's fresh.
+    fn fresh(&self, key: &str) -> Option<Interaction>
+    {
+        let entry = self.store.get(key).ok()??;
+
+        if !entry.is_fresh()
+        {
+            return None;
+        }
+
+        Counters::count(&self.counters.hits);
+        Some(entry.response)
+    }
+
+    /// Counts how a request that waited on another was served.
+    fn waited(&self, interaction: Option<Interaction>) -> Option<Interaction>
+    {
+        Counters::count(if interaction.is_some() { &self.counters.coalesced } else { &self.counters.misses });
+        interaction
+    }
+
+    /// Stores a successful response and hands it to the waiting requests.
+    ///
+    /// Failing to store the response doesn't fail the request, it's only sent
+    /// again next time.
+    fn finish(&self, flight: Flight<'_>, expires: Option<SystemTime>, response: &Response<Vec<u8>>)
+    {
+        let interaction = Interaction::record(response).ok();
+
+        if let Some(interaction) = &interaction
+        {
+            let cacheable = response.status().is_success()
+                && !response.headers().contains_key("MediaWiki-API-Error");
+
+            let stored = cacheable && self.store.put(&flight.key, Entry {
+                response: interaction.clone(),
+                expires,
+            }).is_ok();
+
+            if stored
+            {
+                Counters::count(&self.counters.stored);
+            }
+        }
+
+        flight.finish(interaction);
+    }
+}
+
+impl<T: Transport, S: Store> Transport for Cached<T, S>
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        let key = cache_key(&request);
+        let begin = self.begin(&key, || {
+            let (sender, receiver) = mpsc::channel();
+
+            (Waiter::Thread(sender), receiver)
+        });
+
+        match begin
+        {
+            Begin::Hit(interaction) => interaction.to_response(),
+            Begin::Wait(receiver) => match self.waited(receiver.recv().ok().flatten())
+            {
+                Some(interaction) => interaction.to_response(),
+                None => self.inner.send(request),
+            },
+            Begin::Lead(flight) => {
+                let expires = self.expires(&request);
+                let response = self.inner.send(request)?;
+
+                self.finish(flight, expires, &response);
+                Ok(response)
+            },
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, S> AsyncTransport for Cached<T, S>
+    where T: AsyncTransport + Sync,
+          S: Store + Sync
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(async move {
+            let key = cache_key(&request);
+            let begin = self.begin(&key, || {
+                let (sender, receiver) = oneshot::channel();
+
+                (Waiter::Task(sender), receiver)
+            });
+
+            match begin
+            {
+                Begin::Hit(interaction) => interaction.to_response(),
+                Begin::Wait(receiver) => match self.waited(receiver.await.ok().flatten())
+                {
+                    Some(interaction) => interaction.to_response(),
+                    None => self.inner.send(request).await,
+                },
+                Begin::Lead(flight) => {
+                    let expires = self.expires(&request);
+                    let response = self.inner.send(request).await?;
+
+                    self.finish(flight, expires, &response);
+                    Ok(response)
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod cache_tests
+{
+    use super::*;
+    use crate::client::Client;
+    use crate::requests::Query;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn counting(sent: Arc<AtomicUsize>, delay: Duration)
+        -> impl Fn(Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        move |_request| {
+            let count = sent.fetch_add(1, Ordering::SeqCst) + 1;
+
+            thread::sleep(delay);
+
+            let body = format!("{{\"batchcomplete\":true,\"query\":{{\"pages\":[{{\"ns\":0,\"title\":\"Death\",\"pageid\":{}}}]}}}}", count);
+
+            Ok(Response::new(body.into_bytes()))
+        }
+    }
+
+    fn query(prop: &'static str) -> Query<'static>
+    {
+        let mut query = Query::new();
+
+        query.params.insert("prop".into(), prop.to_string());
+        query.params.insert("titles".into(), "Death".to_string());
+        query
+    }
+
+    fn page_id(client: &Client<Cached<impl Fn(Request<()>) -> Result<Response<Vec<u8>>, Error>, MemoryStore>>, query: &mut Query) -> u64
+    {
+        client.execute(query).unwrap().query.pages.unwrap()[0].page_id.unwrap()
+    }
+
+    #[test]
+    fn keys_ignore_param_order_and_maxlag()
+    {
+        let a = Request::get("https://en.wikipedia.org/w/api.php?titles=Death&action=query&maxlag=5").body(()).unwrap();
+        let b = Request::get("https://en.wikipedia.org/w/api.php?action=query&titles=Death").body(()).unwrap();
+        let other_wiki = Request::get("https://de.wikipedia.org/w/api.php?action=query&titles=Death").body(()).unwrap();
+
+        assert_eq!(cache_key(&a), "en.wikipedia.org GET /w/api.php?action=query&titles=Death");
+        assert_eq!(cache_key(&a), cache_key(&b));
+        assert_ne!(cache_key(&a), cache_key(&other_wiki));
+    }
+
+    #[test]
+    fn serves_fresh_responses_until_their_module_expires()
+    {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let mut cached = Cached::new(counting(sent.clone(), Duration::from_millis(0)), MemoryStore::new(10));
+
+        cached.module_ttl("revisions", Duration::from_secs(0));
+
+        let client = Client::new(cached);
+
+        assert_eq!(page_id(&client, &mut query("info")), 1);
+        assert_eq!(page_id(&client, &mut query("info")), 1);
+        assert_eq!(page_id(&client, &mut query("info|revisions")), 2);
+        assert_eq!(page_id(&client, &mut query("info|revisions")), 3);
+
+        let mut pinned = query("revisions");
+
+        pinned.params.insert("revids".into(), "1234".to_string());
+
+        assert_eq!(page_id(&client, &mut pinned), 4);
+        assert_eq!(page_id(&client, &mut pinned), 4);
+
+        client.transport().invalidate(&query("info").build().unwrap()).unwrap();
+
+        assert_eq!(page_id(&client, &mut query("info")), 5);
+        assert_eq!(sent.load(Ordering::SeqCst), 5);
+
+        let stats = client.transport().stats();
+
+        assert_eq!(stats, CacheStats { hits: 2, misses: 5, coalesced: 0, stored: 5 });
+        assert!((stats.hit_ratio() - 2.0 / 7.0).abs() < 1e-9);
+        assert!(stats.to_prometheus("proxy").contains("proxy_cache_hits_total 2\n"));
+    }
+
+    #[test]
+    fn memory_store_evicts_least_recently_used()
+    {
+        let store = MemoryStore::new(2);
+        let entry = |body: &str| Entry {
+            response: Interaction {
+                status: 200,
+                headers: Vec::new(),
+                body: body.to_string(),
+            },
+            expires: None,
+        };
+
+        store.put("a", entry("a")).unwrap();
+        store.put("b", entry("b")).unwrap();
+        store.get("a").unwrap();
+        store.put("c", entry("c")).unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(store.get("a").unwrap().is_some());
+        assert!(store.get("b").unwrap().is_none());
+        assert_eq!(store.get("c").unwrap().unwrap().response.body, "c");
+    }
+
+    #[test]
+    fn disk_store_persists_entries()
+    {
+        let dir = std::env::temp_dir().join(format!("wikiquery-cache-{}", std::process::id()));
+        let entry = Entry {
+            response: Interaction {
+                status: 200,
+                headers: vec![("content-type".to_string(), "application/json".to_string())],
+                body: "{}".to_string(),
+            },
+            expires: Some(SystemTime::now() + DEFAULT_TTL),
+        };
+
+        DiskStore::new(&dir).unwrap().put("en.wikipedia.org GET /w/api.php?action=query", entry.clone()).unwrap();
+
+        let store = DiskStore::new(&dir).unwrap();
+
+        assert_eq!(store.get("en.wikipedia.org GET /w/api.php?action=query").unwrap(), Some(entry));
+        assert_eq!(store.get("de.wikipedia.org GET /w/api.php?action=query").unwrap(), None);
+
+        store.clear().unwrap();
+
+        assert_eq!(store.get("en.wikipedia.org GET /w/api.php?action=query").unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_store_writers_of_one_key_never_interleave()
+    {
+        let dir = std::env::temp_dir().join(format!("wikiquery-cache-writers-{}", std::process::id()));
+        let store = Arc::new(DiskStore::new(&dir).unwrap());
+
+        let threads: Vec<_> = (1..9)
+            .map(|i| {
+                let store = store.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..20
+                    {
+                        let entry = Entry {
+                            response: Interaction {
+                                status: 200,
+                                headers: Vec::new(),
+                                body: "x".repeat(i * 10_000),
+                            },
+                            expires: None,
+                        };
+
+                        store.put("en.wikipedia.org GET /w/api.php?action=query", entry).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads
+        {
+            thread.join().unwrap();
+        }
+
+        let entry = store.get("en.wikipedia.org GET /w/api.php?action=query").unwrap().unwrap();
+
+        assert_eq!(entry.response.body.len() % 10_000, 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn coalesces_identical_requests_in_flight()
+    {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let client = Arc::new(Client::new(Cached::new(counting(sent.clone(), Duration::from_millis(200)), MemoryStore::new(10))));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+
+                thread::spawn(move || page_id(&client, &mut query("info")))
+            })
+            .collect();
+
+        for thread in threads
+        {
+            assert_eq!(thread.join().unwrap(), 1);
+        }
+
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+        let stats = client.transport().stats();
+
+        // A thread that starts late is served from the store instead.
+        assert_eq!((stats.misses, stats.coalesced + stats.hits), (1, 3));
+    }
+}
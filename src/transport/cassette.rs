@@ -0,0 +1,396 @@
+//! Records responses to a fixture file and replays them in tests.
+//!
+//! A [`Cassette`] in record mode sends requests with another transport and
+//! saves each response to its file, keyed by the canonical form of the
+//! request, see [`canonical_key`]. In replay mode the saved responses are
+//! served back and a request that wasn't recorded fails with
+//! [`UnmatchedRequest`], so tests run offline and always see the same data.
+//!
+//! # Examples
+//! ```no_run
+//! use wikiquery::client::Client;
+//! use wikiquery::requests::Query;
+//! use wikiquery::transport::cassette::Cassette;
+//!
+//! // Replays tests/fixtures/death.json, or records it when WIKIQUERY_RECORD is set.
+//! let cassette = Cassette::from_env("tests/fixtures/death.json", || {
+//!     # let live = |_request| -> Result<http::Response<Vec<u8>>, wikiquery::error::Error> { unimplemented!() };
+//!     /* a live transport */
+//!     # live
+//! }).unwrap();
+//!
+//! let client = Client::new(cassette);
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles("Death")
+//!     .info();
+//!
+//! let response = client.execute(&mut query).unwrap();
+//! ```
+//!
+//! [`Cassette`]: struct.Cassette.html
+//! [`canonical_key`]: fn.canonical_key.html
+//! [`UnmatchedRequest`]: struct.UnmatchedRequest.html
+
+use http::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+use crate::error::Error;
+use super::Transport;
+#[cfg(feature = "async")]
+use super::AsyncTransport;
+
+/// The environment variable that switches [`Cassette::from_env`] to record mode.
+///
+/// [`Cassette::from_env`]: struct.Cassette.html#method.from_env
+pub const RECORD_VAR: &str = "WIKIQUERY_RECORD";
+
+/// The key a request is recorded under.
+///
+/// This is the host, method and path of the request and the params of its
+/// query string. The params are sorted, so requests built from the same params
+/// always match, whatever order they were added in, and the host keeps the
+/// responses of different wikis apart.
+pub fn canonical_key<B>(request: &Request<B>) -> String
+{
+    let uri = request.uri();
+    let mut params: Vec<&str> = uri.query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .collect();
+
+    params.sort();
+
+    format!("{} {} {}?{}", uri.host().unwrap_or(""), request.method(), uri.path(), params.join("&"))
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction
+{
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Interaction
+{
+    pub(crate) fn record(response: &Response<Vec<u8>>) -> Result<Interaction, Error>
+    {
+        let headers = response.headers().iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = String::from_utf8(response.body().clone())
+            .map_err(|err| Error::Transport(Box::new(err)))?;
+
+        Ok(Interaction {
+            status: response.status().as_u16(),
+            headers,
+            body,
+        })
+    }
+
+    pub(crate) fn to_response(&self) -> Result<Response<Vec<u8>>, Error>
+    {
+        let mut builder = Response::builder();
+
+        builder.status(self.status);
+
+        for (name, value) in &self.headers
+        {
+            builder.header(name.as_str(), value.as_str());
+        }
+
+        Ok(builder.body(self.body.clone().into_bytes())?)
+    }
+}
+
+/// A request that has no recorded response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmatchedRequest
+{
+    /// The canonical key of the request.
+    pub key: String,
+    pub cassette: PathBuf,
+}
+
+impl fmt::Display for UnmatchedRequest
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "no response recorded for {} in {}", self.key, self.cassette.display())
+    }
+}
+
+impl std::error::Error for UnmatchedRequest {}
+
+/// A transport that fails every request, used by cassettes in replay mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Offline;
+
+impl Transport for Offline
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        Err(Error::Transport(format!("offline, can't send {}", request.uri()).into()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for Offline
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(futures::future::ready(Transport::send(self, request)))
+    }
+}
+
+/// Records or replays the responses of a transport.
+pub struct Cassette<T>
+{
+    path: PathBuf,
+    inner: Option<T>,
+    interactions: Mutex<BTreeMap<String, Interaction>>,
+}
+
+impl Cassette<Offline>
+{
+    /// Serves the responses saved in `path`.
+    pub fn replay<P: Into<PathBuf>>(path: P) -> io::Result<Cassette<Offline>>
+    {
+        let path = path.into();
+        let interactions = serde_json::from_slice(&fs::read(&path)?)?;
+
+        Ok(Cassette {
+            path,
+            inner: None,
+            interactions: Mutex::new(interactions),
+        })
+    }
+}
+
+impl<T> Cassette<T>
+{
+    /// Sends requests with `inner` and saves their responses to `path`,
+    /// replacing what was recorded before.
+    pub fn record<P: Into<PathBuf>>(path: P, inner: T) -> Cassette<T>
+    {
+        Cassette {
+            path: path.into(),
+            inner: Some(inner),
+            interactions: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records with the transport made by `inner` when the `WIKIQUERY_RECORD`
+    /// environment variable is set, and replays `path` otherwise.
+    pub fn from_env<P, F>(path: P, inner: F) -> io::Result<Cassette<T>>
+        where P: Into<PathBuf>,
+              F: FnOnce() -> T
+    {
+        if std::env::var_os(RECORD_VAR).is_some()
+        {
+            Ok(Cassette::record(path, inner()))
+        }
+        else
+        {
+            let replay = Cassette::replay(path)?;
+
+            Ok(Cassette {
+                path: replay.path,
+                inner: None,
+                interactions: replay.interactions,
+            })
+        }
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    pub fn is_recording(&self) -> bool
+    {
+        self.inner.is_some()
+    }
+
+    /// The canonical keys of the recorded requests.
+    pub fn keys(&self) -> Vec<String>
+    {
+        self.interactions.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn replay_response(&self, request: &Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        let key = canonical_key(request);
+
+        match self.interactions.lock().unwrap().get(&key)
+        {
+            Some(interaction) => interaction.to_response(),
+            None => Err(Error::Transport(Box::new(UnmatchedRequest {
+                key,
+                cassette: self.path.clone(),
+            }))),
+        }
+    }
+
+    fn record_response(&self, key: String, response: &Response<Vec<u8>>) -> Result<(), Error>
+    {
+        let mut interactions = self.interactions.lock().unwrap();
+
+        interactions.insert(key, Interaction::record(response)?);
+
+        if let Some(parent) = self.path.parent()
+        {
+            fs::create_dir_all(parent).map_err(|err| Error::Transport(Box::new(err)))?;
+        }
+
+        fs::write(&self.path, serde_json::to_vec_pretty(&*interactions)?)
+            .map_err(|err| Error::Transport(Box::new(err)))
+    }
+}
+
+impl<T: Transport> Transport for Cassette<T>
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        match &self.inner
+        {
+            Some(inner) => {
+                let key = canonical_key(&request);
+                let response = inner.send(request)?;
+
+                self.record_response(key, &response)?;
+
+                Ok(response)
+            },
+            None => self.replay_response(&request),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: AsyncTransport + Sync> AsyncTransport for Cassette<T>
+{
+    fn send(&self, request: Request<()>) -> BoxFuture<'_, Result<Response<Vec<u8>>, Error>>
+    {
+        Box::pin(async move {
+            match &self.inner
+            {
+                Some(inner) => {
+                    let key = canonical_key(&request);
+                    let response = inner.send(request).await?;
+
+                    self.record_response(key, &response)?;
+
+                    Ok(response)
+                },
+                None => self.replay_response(&request),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod cassette_tests
+{
+    use super::*;
+    use crate::client::Client;
+    use crate::requests::Query;
+
+    fn temp_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("wikiquery-{}-{}.json", name, std::process::id()))
+    }
+
+    fn query(title: &str) -> Query<'static>
+    {
+        let mut query = Query::new();
+
+        query.pages()
+            .titles(title.to_string())
+            .info();
+
+        query
+    }
+
+    #[test]
+    fn canonical_key_sorts_params()
+    {
+        let a = Request::get("https://en.wikipedia.org/w/api.php?&titles=Death&action=query&prop=info").body(()).unwrap();
+        let b = Request::get("https://en.wikipedia.org/w/api.php?action=query&prop=info&titles=Death").body(()).unwrap();
+
+        assert_eq!(canonical_key(&a), canonical_key(&b));
+        assert_eq!(canonical_key(&a), "en.wikipedia.org GET /w/api.php?action=query&prop=info&titles=Death");
+    }
+
+    #[test]
+    fn canonical_key_keeps_hosts_apart()
+    {
+        let en = Request::get("https://en.wikipedia.org/w/api.php?action=query&titles=Tod").body(()).unwrap();
+        let de = Request::get("https://de.wikipedia.org/w/api.php?action=query&titles=Tod").body(()).unwrap();
+
+        assert_ne!(canonical_key(&en), canonical_key(&de));
+
+        let path = temp_path("cassette-hosts");
+        let live = |request: Request<()>| {
+            let body = format!("{{\"batchcomplete\":true,\"query\":{{\"host\":\"{}\"}}}}", request.uri().host().unwrap());
+
+            Ok(Response::new(body.into_bytes()))
+        };
+        let recorder = Cassette::record(&path, live);
+
+        Transport::send(&recorder, en).unwrap();
+
+        let player = Cassette::replay(&path).unwrap();
+
+        assert!(Transport::send(&player, de).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replays_recorded_responses()
+    {
+        let path = temp_path("cassette");
+        let live = |request: Request<()>| {
+            let title = if request.uri().query().unwrap().contains("titles=Death") { "Death" } else { "Life" };
+            let body = format!("{{\"batchcomplete\":true,\"query\":{{\"pages\":[{{\"ns\":0,\"title\":\"{}\",\"pageid\":8221}}]}}}}", title);
+
+            Ok(Response::new(body.into_bytes()))
+        };
+
+        let recorder = Client::new(Cassette::record(&path, live));
+        let recorded = recorder.execute(&mut query("Death")).unwrap();
+
+        recorder.execute(&mut query("Life")).unwrap();
+        assert!(recorder.transport().is_recording());
+
+        let player = Client::new(Cassette::replay(&path).unwrap());
+
+        assert_eq!(player.transport().keys().len(), 2);
+        assert_eq!(player.execute(&mut query("Death")).unwrap(), recorded);
+
+        match player.execute(&mut query("Birth"))
+        {
+            Err(Error::Transport(err)) => {
+                let unmatched = err.downcast_ref::<UnmatchedRequest>().unwrap();
+                assert!(unmatched.key.contains("titles=Birth"));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
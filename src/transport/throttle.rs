@@ -0,0 +1,139 @@
+//! Spaces out the requests of another transport.
+//!
+//! [`Throttled`] lets a request start only once an interval has passed since
+//! the previous one, across every thread sharing it. Wrap it in a
+//! [`Cached`] so responses served from the cache aren't held back.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "fake")]
+//! # {
+//! use wikiquery::client::Client;
+//! use wikiquery::fake::{FakePage, FakeWiki};
+//! use wikiquery::requests::Query;
+//! use wikiquery::transport::cache::{Cached, MemoryStore};
+//! use wikiquery::transport::throttle::Throttled;
+//!
+//! let mut wiki = FakeWiki::new();
+//!
+//! wiki.add_page(FakePage::new("Death"));
+//!
+//! let client = Client::new(Cached::new(Throttled::per_second(wiki, 10.0), MemoryStore::new(100)));
+//! let mut query = Query::new();
+//!
+//! query.pages()
+//!     .titles("Death")
+//!     .info();
+//!
+//! client.execute(&mut query).unwrap();
+//! # }
+//! ```
+//!
+//! [`Throttled`]: struct.Throttled.html
+//! [`Cached`]: ../cache/struct.Cached.html
+
+use http::{Request, Response};
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use super::Transport;
+
+/// Sends requests with another transport, at most one per interval.
+#[derive(Debug)]
+pub struct Throttled<T>
+{
+    inner: T,
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl<T> Throttled<T>
+{
+    pub fn new(inner: T, interval: Duration) -> Throttled<T>
+    {
+        Throttled {
+            inner,
+            interval,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Lets at most `requests` start per second. Zero or less doesn't limit.
+    pub fn per_second(inner: T, requests: f64) -> Throttled<T>
+    {
+        let interval = if requests > 0.0 { Duration::from_secs_f64(1.0 / requests) } else { Duration::from_secs(0) };
+
+        Throttled::new(inner, interval)
+    }
+
+    pub fn interval(&self) -> Duration
+    {
+        self.interval
+    }
+
+    pub fn inner(&self) -> &T
+    {
+        &self.inner
+    }
+
+    /// Takes the next free slot and returns how long to wait for it.
+    fn reserve(&self) -> Duration
+    {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap();
+        let start = next.map_or(now, |next| next.max(now));
+
+        *next = Some(start + self.interval);
+
+        start - now
+    }
+}
+
+impl<T: Transport> Transport for Throttled<T>
+{
+    fn send(&self, request: Request<()>) -> Result<Response<Vec<u8>>, Error>
+    {
+        let wait = self.reserve();
+
+        if wait > Duration::from_secs(0)
+        {
+            thread::sleep(wait);
+        }
+
+        self.inner.send(request)
+    }
+}
+
+#[cfg(test)]
+mod throttle_tests
+{
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn spaces_out_requests_across_threads()
+    {
+        let transport = |_request| Ok(Response::new(Vec::new()));
+        let throttled = Arc::new(Throttled::new(transport, Duration::from_millis(40)));
+        let started = Instant::now();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let throttled = throttled.clone();
+
+                thread::spawn(move || throttled.send(Request::get("/w/api.php").body(()).unwrap()).unwrap())
+            })
+            .collect();
+
+        for thread in threads
+        {
+            thread.join().unwrap();
+        }
+
+        assert!(started.elapsed() >= Duration::from_millis(120));
+        assert_eq!(Throttled::per_second((), 4.0).interval(), Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,150 @@
+//! Identifies the application sending requests.
+//!
+//! Wikimedia's [User-Agent policy] asks every client for a `User-Agent` naming
+//! the application, its version and a way to contact its operator. Generic
+//! agents may be throttled or blocked. The [`Client`] sends the crate's own
+//! agent unless it's given one.
+//!
+//! # Examples
+//! ```
+//! use wikiquery::user_agent::UserAgent;
+//!
+//! let mut agent = UserAgent::new("CategoryMapper", "1.2", "https://example.org/mapper; mapper@example.org");
+//!
+//! agent.api_user_agent("CategoryMapper/1.2 (mapper@example.org)");
+//!
+//! assert_eq!(
+//!     agent.header(),
+//!     format!("CategoryMapper/1.2 (https://example.org/mapper; mapper@example.org) wikiquery/{}", env!("CARGO_PKG_VERSION"))
+//! );
+//! ```
+//!
+//! [User-Agent policy]: https://meta.wikimedia.org/wiki/User-Agent_policy
+//! [`Client`]: ../client/struct.Client.html
+
+use http::header::{HeaderName, HeaderValue, USER_AGENT};
+use http::Request;
+
+use crate::error::Error;
+
+/// Where the crate can be found, used as the contact of the default agent.
+pub const REPOSITORY: &str = "https://github.com/dastardlychimp/wikiquery";
+
+/// The `User-Agent` and `Api-User-Agent` headers sent with every request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserAgent
+{
+    app: String,
+    version: String,
+    contact: String,
+    api_user_agent: Option<String>,
+}
+
+impl Default for UserAgent
+{
+    /// The crate itself, contacted through its repository.
+    fn default() -> UserAgent
+    {
+        UserAgent::new("wikiquery", env!("CARGO_PKG_VERSION"), REPOSITORY)
+    }
+}
+
+impl UserAgent
+{
+    /// `contact` should be a url or email address, or both separated by `; `.
+    pub fn new<A, V, C>(app: A, version: V, contact: C) -> UserAgent
+        where A: Into<String>,
+              V: Into<String>,
+              C: Into<String>,
+    {
+        UserAgent {
+            app: app.into(),
+            version: version.into(),
+            contact: contact.into(),
+            api_user_agent: None,
+        }
+    }
+
+    /// Also sends `Api-User-Agent`, which the api reads in place of
+    /// `User-Agent` when a browser doesn't let the page set it.
+    pub fn api_user_agent<S: Into<String>>(&mut self, agent: S) -> &mut Self
+    {
+        self.api_user_agent = Some(agent.into());
+        self
+    }
+
+    /// The value of the `User-Agent` header, ending with the crate's own
+    /// name and version.
+    pub fn header(&self) -> String
+    {
+        let agent = format!("{}/{} ({})", self.app, self.version, self.contact);
+
+        if self.app == "wikiquery"
+        {
+            agent
+        }
+        else
+        {
+            format!("{} wikiquery/{}", agent, env!("CARGO_PKG_VERSION"))
+        }
+    }
+
+    /// Sets the headers on the request, unless it already has them.
+    pub fn apply<B>(&self, request: &mut Request<B>) -> Result<(), Error>
+    {
+        let headers = request.headers_mut();
+
+        if !headers.contains_key(USER_AGENT)
+        {
+            headers.insert(USER_AGENT, HeaderValue::from_str(&self.header()).map_err(http::Error::from)?);
+        }
+
+        if let Some(agent) = &self.api_user_agent
+        {
+            let name = HeaderName::from_static("api-user-agent");
+
+            if !headers.contains_key(&name)
+            {
+                headers.insert(name, HeaderValue::from_str(agent).map_err(http::Error::from)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod user_agent_tests
+{
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_crate()
+    {
+        let mut request = Request::new(());
+
+        UserAgent::default().apply(&mut request).unwrap();
+
+        assert_eq!(request.headers()["user-agent"], format!("wikiquery/{} ({})", env!("CARGO_PKG_VERSION"), REPOSITORY).as_str());
+        assert!(!request.headers().contains_key("api-user-agent"));
+    }
+
+    #[test]
+    fn keeps_headers_already_set()
+    {
+        let mut agent = UserAgent::new("Mapper", "1.0", "mapper@example.org");
+        let mut request = Request::builder()
+            .header("User-Agent", "Custom/2.0")
+            .body(())
+            .unwrap();
+
+        agent.api_user_agent("Mapper/1.0");
+        agent.apply(&mut request).unwrap();
+
+        assert_eq!(request.headers()["user-agent"], "Custom/2.0");
+        assert_eq!(request.headers()["api-user-agent"], "Mapper/1.0");
+
+        agent.api_user_agent("Mapper\n");
+        assert!(agent.apply(&mut Request::new(())).is_err());
+    }
+}
@@ -2,7 +2,7 @@ use wikiquery;
 use wikiquery::requests::Query;
 
 mod helpers;
-use helpers::send_successful_query;
+use helpers::send_recorded_query;
 
 
 mod category_members_tests
@@ -27,7 +27,7 @@ mod category_members_tests
 
         let uri = query.uri().unwrap();
 
-        let response = send_successful_query(uri);
+        let response = send_recorded_query("category_members/max_data", uri);
         let category_members = response.query.category_members.unwrap();
         let first_member = &category_members[0];
 
@@ -53,7 +53,7 @@ mod category_members_tests
         
         let uri = query.uri().unwrap();
 
-        let response = send_successful_query(uri);
+        let response = send_recorded_query("category_members/warning", uri);
         
         let warnings = response.warnings
             .unwrap()
@@ -1,4 +1,8 @@
+#![allow(dead_code)]
+
 use wikiquery::responses::Query;
+use wikiquery::transport::Transport;
+use wikiquery::transport::cassette::Cassette;
 
 use http;
 use hyper;
@@ -51,4 +55,33 @@ pub async fn body_to_string(mut body: Body) -> String
     }
 
     string
-}
\ No newline at end of file
+}
+
+/// Sends a request to the live wiki, for recording cassettes.
+pub fn live(request: http::Request<()>) -> Result<http::Response<Vec<u8>>, wikiquery::error::Error>
+{
+    let response = async {
+        let resp = send_query(request.uri().clone()).await
+            .map_err(|err| wikiquery::error::Error::Transport(Box::new(err)))?;
+        let (parts, body) = resp.into_parts();
+        let body = body_to_string(body).await;
+
+        Ok::<_, wikiquery::error::Error>(http::Response::from_parts(parts, body.into_bytes()))
+    };
+
+    RUNTIME.block_on(response)
+}
+
+/// Replays the response to `uri` saved in `tests/fixtures/<cassette>.json`,
+/// or records it from the live wiki when `WIKIQUERY_RECORD` is set.
+pub fn send_recorded_query(cassette: &str, uri: http::Uri) -> Query
+{
+    let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), cassette);
+    let cassette = Cassette::from_env(path, || live).unwrap();
+    let request = http::Request::get(uri).body(()).unwrap();
+    let response = cassette.send(request).unwrap();
+
+    assert!(response.status().is_success());
+
+    serde_json::from_slice(response.body()).unwrap()
+}
@@ -2,7 +2,7 @@ use wikiquery;
 use wikiquery::requests::Query;
 
 mod helpers;
-use helpers::send_successful_query;
+use helpers::send_recorded_query;
 
 
 mod pages_tests
@@ -43,7 +43,7 @@ mod pages_tests
         
         let uri = query.uri().unwrap();
 
-        let response = send_successful_query(uri);
+        let response = send_recorded_query("pages/all_info", uri);
 
         let pages = response.query.pages.unwrap();
         let first_page = &pages[0];
@@ -52,8 +52,8 @@ mod pages_tests
 
         assert!(response.warnings.is_none());
 
-        assert_eq!(first_page.ns, 0);
-        assert_eq!(first_page.page_id, 217225);
+        assert_eq!(first_page.ns, Some(0));
+        assert_eq!(first_page.page_id, Some(217225));
         assert_eq!(first_page.title, "Main page".to_string());
         assert!(first_page.missing.is_none());
 
@@ -84,7 +84,7 @@ mod pages_tests
 
         let uri = query.uri().unwrap();
 
-        let response = send_successful_query(uri);
+        let response = send_recorded_query("pages/description", uri);
 
         println!("{:?}", &response);
 
@@ -93,8 +93,8 @@ mod pages_tests
         let pages = response.query.pages.unwrap();
         let first_page = &pages[0];
 
-        assert_eq!(first_page.ns, 0);
-        assert_eq!(first_page.page_id, 8221);
+        assert_eq!(first_page.ns, Some(0));
+        assert_eq!(first_page.page_id, Some(8221));
         assert_eq!(first_page.title, "Death".to_string());
         assert!(first_page.missing.is_none());
 
@@ -116,7 +116,7 @@ mod pages_tests
 
         let uri = query.uri().unwrap();
 
-        let response = send_successful_query(uri);
+        let response = send_recorded_query("pages/extracts_chars", uri);
         
         println!("{:?}", &response);
 
@@ -125,8 +125,8 @@ mod pages_tests
         let pages = response.query.pages.unwrap();
         let first_page = &pages[0];
 
-        assert_eq!(first_page.ns, 0);
-        assert_eq!(first_page.page_id, 8221);
+        assert_eq!(first_page.ns, Some(0));
+        assert_eq!(first_page.page_id, Some(8221));
         assert_eq!(first_page.title, "Death".to_string());
         assert!(first_page.missing.is_none());
 